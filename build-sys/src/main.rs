@@ -1,11 +1,15 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::*;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
+use std::time::{Duration, Instant, SystemTime};
 use walkdir::WalkDir;
+use rayon::prelude::*;
 use reqwest::blocking::Client;
 
 #[derive(Parser)]
@@ -13,6 +17,10 @@ use reqwest::blocking::Client;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Print detailed progress, such as per-file build timings
+    #[arg(short, long, global = true)]
+    verbose: bool,
 }
 
 #[derive(Subcommand)]
@@ -27,12 +35,43 @@ enum Commands {
         /// Enable release optimizations
         #[arg(long)]
         release: bool,
+        /// Recompile every file, even ones whose objects are up to date
+        #[arg(long)]
+        force: bool,
+        /// Output format for build progress and diagnostics
+        #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+        message_format: MessageFormat,
+        /// Operating system to generate code for (linux or macos), saved to
+        /// zed.json for later builds
+        #[arg(long)]
+        target: Option<String>,
     },
     /// Run the current project
     Run {
         /// Enable release optimizations
         #[arg(long)]
         release: bool,
+        /// Recompile every file, even ones whose objects are up to date
+        #[arg(long)]
+        force: bool,
+        /// Operating system to generate code for (linux or macos), saved to
+        /// zed.json for later builds
+        #[arg(long)]
+        target: Option<String>,
+        /// Arguments passed to the program, after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Build and run the project's @test functions
+    Test,
+    /// Generate documentation for the current project
+    Doc {
+        /// Open the documentation in a browser after generating it
+        #[arg(long)]
+        open: bool,
+        /// Include private functions in the documentation
+        #[arg(long)]
+        private: bool,
     },
     /// Clean the project
     Clean,
@@ -40,16 +79,84 @@ enum Commands {
     InstallStd,
 }
 
+/// How `build` reports progress: colored lines for people, or one JSON
+/// event per line for editors and other tools
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum MessageFormat {
+    Human,
+    Json,
+}
+
 #[derive(Serialize, Deserialize)]
 struct ZedConfig {
     name: String,
     version: String,
     target: String,
+    /// Operating system passed to zedc as `--target`; the host when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+    /// Directories, relative to the project root, searched for includes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    include_dirs: Vec<String>,
+    /// Assembler to run instead of `as`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    assembler: Option<String>,
+    /// Linker to run instead of `ld` (or `cc` when targeting macOS)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    linker: Option<String>,
+    /// Extra arguments for the linker, passed after the object files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    linker_flags: Vec<String>,
+}
+
+/// When a file was last modified
+fn modified(path: &Path) -> Result<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("Failed to read the modification time of {}", path.display()))
+}
+
+/// The most recent modification time of any file under a directory
+fn newest_modification(dir: &Path) -> Result<SystemTime> {
+    let mut newest = SystemTime::UNIX_EPOCH;
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            newest = newest.max(modified(entry.path())?);
+        }
+    }
+    Ok(newest)
+}
+
+/// Operating systems zedc can generate code for
+const SUPPORTED_TARGETS: &[&str] = &["linux", "macos"];
+
+/// Parses a `zed.json` manifest. Strict JSON is tried first; if that fails,
+/// the manifest is parsed as JSON5 so it may contain comments and trailing
+/// commas.
+fn parse_manifest<T: DeserializeOwned>(content: &str) -> Result<T> {
+    match serde_json::from_str(content) {
+        Ok(value) => Ok(value),
+        Err(_) => Ok(json5::from_str(content)?),
+    }
 }
 
 struct ZedProject {
     root: PathBuf,
     config: ZedConfig,
+    verbose: bool,
+    message_format: MessageFormat,
+}
+
+/// One source file compiled and assembled by `compile_and_assemble`
+struct FileBuild {
+    lines: usize,
+    /// zedc's exit status and diagnostics, reported in file order
+    zedc: Output,
+    compile_time: Duration,
+    /// Whether `as` succeeded; not run when zedc failed
+    assembled: Result<()>,
+    assemble_time: Duration,
 }
 
 impl ZedProject {
@@ -59,8 +166,18 @@ impl ZedProject {
             name: name.to_string(),
             version: "0.1.0".to_string(),
             target: "main".to_string(),
+            platform: None,
+            include_dirs: Vec::new(),
+            assembler: None,
+            linker: None,
+            linker_flags: Vec::new(),
         };
-        Ok(Self { root, config })
+        Ok(Self {
+            root,
+            config,
+            verbose: false,
+            message_format: MessageFormat::Human,
+        })
     }
 
     fn install_stdlib() -> Result<()> {
@@ -155,15 +272,149 @@ println("Hello from Zed!");
         let config_path = path.join("zed.json");
         let config_content = fs::read_to_string(&config_path)
             .context("Failed to read zed.json")?;
-        let config: ZedConfig = serde_json::from_str(&config_content)
+        let config: ZedConfig = parse_manifest(&config_content)
             .context("Failed to parse zed.json")?;
         Ok(Self {
             root: path.to_path_buf(),
             config,
+            verbose: false,
+            message_format: MessageFormat::Human,
         })
     }
 
-    fn build(&self, release: bool) -> Result<()> {
+    fn with_message_format(mut self, message_format: MessageFormat) -> Self {
+        self.message_format = message_format;
+        self
+    }
+
+    fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Switches the project to a new code generation target and saves it to
+    /// `zed.json`, so later builds without `--target` keep using it
+    fn with_target(mut self, target: Option<String>) -> Result<Self> {
+        let Some(target) = target else {
+            return Ok(self);
+        };
+        Self::check_target(&target)?;
+        if self.config.platform.as_deref() != Some(target.as_str()) {
+            self.save_platform(&target)?;
+            self.config.platform = Some(target);
+        }
+        Ok(self)
+    }
+
+    /// Sets `platform` in `zed.json`, leaving every other field as written,
+    /// including ones `ZedConfig` doesn't know about. A JSON5 manifest isn't
+    /// rewritten, since that would lose its comments; the target then only
+    /// applies to this build.
+    fn save_platform(&self, target: &str) -> Result<()> {
+        let config_path = self.root.join("zed.json");
+        let config_content = fs::read_to_string(&config_path)
+            .context("Failed to read zed.json")?;
+        let Ok(mut manifest) =
+            serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&config_content)
+        else {
+            eprintln!(
+                "{} zed.json has comments or trailing commas, so the target wasn't saved to it; \
+                 add \"platform\": \"{}\" to it to keep building for {}",
+                "Warning:".yellow(),
+                target,
+                target
+            );
+            return Ok(());
+        };
+
+        manifest.insert("platform".to_string(), target.into());
+        fs::write(&config_path, serde_json::to_string_pretty(&manifest)?)
+            .context("Failed to write zed.json")
+    }
+
+    /// Rejects targets zedc can't generate code for, before anything is
+    /// compiled or assembled
+    fn check_target(target: &str) -> Result<()> {
+        if !SUPPORTED_TARGETS.contains(&target) {
+            anyhow::bail!(
+                "Unsupported target `{}` (expected one of: {})",
+                target,
+                SUPPORTED_TARGETS.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the executable is linked for macOS, either explicitly or
+    /// because that's the host and no target is set
+    fn targets_macos(&self) -> bool {
+        match self.config.platform.as_deref() {
+            Some(target) => target == "macos",
+            None => cfg!(target_os = "macos"),
+        }
+    }
+
+    /// The configured include search directories, resolved against the root
+    fn include_dirs(&self) -> Vec<PathBuf> {
+        self.config
+            .include_dirs
+            .iter()
+            .map(|dir| self.root.join(dir))
+            .collect()
+    }
+
+    /// Prints a human-readable progress line; JSON output uses `event` instead
+    fn status(&self, label: ColoredString, message: impl std::fmt::Display) {
+        if self.message_format == MessageFormat::Human {
+            println!("{} {}", label, message);
+        }
+    }
+
+    /// Prints one build event as a line of JSON, when JSON output is on
+    fn event(&self, reason: &str, mut fields: serde_json::Value) {
+        if self.message_format == MessageFormat::Json {
+            fields["reason"] = reason.into();
+            println!("{}", fields);
+        }
+    }
+
+    fn build(&self, release: bool, force: bool) -> Result<()> {
+        let build_start = Instant::now();
+        let result = self.build_artifacts(release, force);
+
+        let (files, lines) = result.as_ref().map_or((0, 0), |summary| *summary);
+        if result.is_ok() {
+            self.status(
+                "Finished:".green(),
+                format!(
+                    "Compiled {} file{} ({} lines) in {:.2}s",
+                    files,
+                    if files == 1 { "" } else { "s" },
+                    lines,
+                    build_start.elapsed().as_secs_f64()
+                ),
+            );
+        }
+        self.event(
+            "finished",
+            serde_json::json!({
+                "success": result.is_ok(),
+                "files": files,
+                "lines": lines,
+                "duration_secs": build_start.elapsed().as_secs_f64(),
+            }),
+        );
+
+        result.map(|_| ())
+    }
+
+    /// Compiles, assembles and links the project, returning how many files
+    /// and lines were compiled. Files whose objects are newer than everything
+    /// they depend on are skipped unless `force` is set.
+    fn build_artifacts(&self, release: bool, force: bool) -> Result<(usize, usize)> {
+        if let Some(target) = &self.config.platform {
+            Self::check_target(target)?;
+        }
         self.check_stdlib()?;
 
         let target_dir = self.root.join("target");
@@ -171,14 +422,8 @@ println("Hello from Zed!");
         let build_dir = target_dir.join(build_type);
         fs::create_dir_all(&build_dir)?;
 
-        // Find all .zed files
-        let mut zed_files = Vec::new();
-        for entry in WalkDir::new(self.root.join("src")) {
-            let entry = entry?;
-            if entry.path().extension().map_or(false, |ext| ext == "zed") {
-                zed_files.push(entry.path().to_path_buf());
-            }
-        }
+        let mut zed_files = self.source_files()?;
+        self.check_include_cycles(&zed_files)?;
 
         // Sort files so main.zed is last (for linking order)
         zed_files.sort_by(|a, b| {
@@ -187,7 +432,10 @@ println("Hello from Zed!");
             a_is_main.cmp(&b_is_main)
         });
 
-        // Compile and assemble each file
+        let stdlib_modified = newest_modification(&Self::get_stdlib_path()?)?;
+
+        // Work out which files need compiling
+        let mut stale = Vec::new();
         for source_path in &zed_files {
             let asm_path = build_dir.join(
                 source_path
@@ -198,33 +446,384 @@ println("Hello from Zed!");
             );
             let obj_path = asm_path.with_extension("o");
 
-            println!("{} {}", "Compiling:".blue(), source_path.display());
-            self.compile_to_asm(source_path, &asm_path)?;
+            if !force && self.is_fresh(source_path, &obj_path, stdlib_modified)? {
+                if self.verbose {
+                    self.status("Fresh:".dimmed(), source_path.display());
+                }
+                self.event(
+                    "artifact",
+                    serde_json::json!({
+                        "kind": "object",
+                        "file": source_path,
+                        "path": obj_path,
+                        "fresh": true,
+                    }),
+                );
+                continue;
+            }
+            stale.push((source_path, asm_path, obj_path));
+        }
+
+        // Each file compiles independently, so they're built in parallel.
+        // Progress is reported afterwards in file order, so the output
+        // doesn't depend on which file finished first.
+        let builds: Vec<Result<FileBuild>> = stale
+            .par_iter()
+            .map(|(source_path, asm_path, obj_path)| {
+                self.compile_and_assemble(source_path, asm_path, obj_path)
+            })
+            .collect();
+
+        let mut compiled_files = 0;
+        let mut total_lines = 0;
+        let mut errors = Vec::new();
+        for ((source_path, asm_path, obj_path), build) in stale.iter().zip(builds) {
+            self.status("Compiling:".blue(), source_path.display());
+            self.event("compile-start", serde_json::json!({ "file": source_path }));
+            let build = match build {
+                Ok(build) => build,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            if let Err(e) = self.report_zedc(source_path, &build.zedc) {
+                errors.push(e);
+                continue;
+            }
 
-            println!("{} {}", "Assembling:".blue(), asm_path.display());
-            self.assemble(&asm_path, &obj_path)?;
+            self.status("Assembling:".blue(), asm_path.display());
+            if let Err(e) = build.assembled {
+                errors.push(e);
+                continue;
+            }
+            compiled_files += 1;
+            total_lines += build.lines;
+            self.event(
+                "artifact",
+                serde_json::json!({
+                    "kind": "object",
+                    "file": source_path,
+                    "path": obj_path,
+                    "fresh": false,
+                }),
+            );
+
+            if self.verbose && self.message_format == MessageFormat::Human {
+                println!(
+                    "  {} lines, compiled in {:.2?}, assembled in {:.2?}",
+                    build.lines, build.compile_time, build.assemble_time
+                );
+            }
+        }
+
+        if errors.len() == 1 {
+            return Err(errors.remove(0));
+        }
+        if !errors.is_empty() {
+            let messages: Vec<String> = errors.iter().map(|e| format!("{:#}", e)).collect();
+            anyhow::bail!("{}", messages.join("\n"));
         }
 
         // Link
         let output_path = build_dir.join(&self.config.target);
-        println!("{} {}", "Linking:".blue(), output_path.display());
+        self.status("Linking:".blue(), output_path.display());
+        let phase_start = Instant::now();
         self.link(&build_dir, &output_path)?;
+        if self.verbose && self.message_format == MessageFormat::Human {
+            println!("  linked in {:.2?}", phase_start.elapsed());
+        }
+        self.event(
+            "artifact",
+            serde_json::json!({ "kind": "executable", "path": output_path }),
+        );
+
+        Ok((compiled_files, total_lines))
+    }
+
+    /// Whether an object file is newer than everything its source depends
+    /// on: the source itself, the local files it includes (directly or
+    /// through other includes), `zed.json` and the installed standard
+    /// library. A missing object or include counts as out of date, so zedc
+    /// gets to report the missing include.
+    fn is_fresh(&self, source_path: &Path, obj_path: &Path, stdlib_modified: SystemTime) -> Result<bool> {
+        let Ok(built) = fs::metadata(obj_path).and_then(|metadata| metadata.modified()) else {
+            return Ok(false);
+        };
 
-        println!("{} Build completed successfully!", "Success:".green());
+        let include_dirs = self.include_dirs();
+        let mut newest = stdlib_modified.max(modified(&self.root.join("zed.json"))?);
+        let mut pending = vec![source_path.to_path_buf()];
+        let mut seen = HashSet::new();
+        while let Some(file) = pending.pop() {
+            if !seen.insert(file.clone()) {
+                continue;
+            }
+            if !file.exists() {
+                return Ok(false);
+            }
+            newest = newest.max(modified(&file)?);
+            pending.extend(Self::local_includes(&file, &include_dirs)?);
+        }
+        Ok(built > newest)
+    }
+
+    /// All `.zed` files under `src/`
+    fn source_files(&self) -> Result<Vec<PathBuf>> {
+        let mut zed_files = Vec::new();
+        for entry in WalkDir::new(self.root.join("src")) {
+            let entry = entry?;
+            if entry.path().extension().is_some_and(|ext| ext == "zed") {
+                zed_files.push(entry.path().to_path_buf());
+            }
+        }
+        Ok(zed_files)
+    }
+
+    /// Builds a test runner for every source file that declares `@test`
+    /// functions and runs it. zedc's `--test` mode generates the runner, which
+    /// calls exactly the attributed functions.
+    fn test(&self) -> Result<()> {
+        let test_start = Instant::now();
+        if let Some(target) = &self.config.platform {
+            Self::check_target(target)?;
+        }
+        self.check_stdlib()?;
+
+        let test_dir = self.root.join("target").join("test");
+        fs::create_dir_all(&test_dir)?;
+
+        let zed_files = self.source_files()?;
+        self.check_include_cycles(&zed_files)?;
+
+        let mut runners = 0;
+        let mut failed = Vec::new();
+        for source_path in &zed_files {
+            let source = fs::read_to_string(source_path)
+                .with_context(|| format!("Failed to read {}", source_path.display()))?;
+            if !source.lines().any(|line| line.trim_start().starts_with("@test")) {
+                continue;
+            }
+            runners += 1;
+
+            let stem = source_path.file_stem().unwrap_or_default().to_string_lossy();
+            let asm_path = test_dir.join(format!("{}.test.s", stem));
+            let obj_path = asm_path.with_extension("o");
+            let executable = test_dir.join(format!("{}-test", stem));
+
+            println!("{} {}", "Testing:".blue(), source_path.display());
+            self.compile_to_asm(source_path, &asm_path, true)?;
+            self.assemble(&asm_path, &obj_path)?;
+            self.link_objects(&[obj_path], &executable)?;
+
+            let status = Command::new(&executable)
+                .status()
+                .with_context(|| format!("Failed to run {}", executable.display()))?;
+            if !status.success() {
+                failed.push(source_path.clone());
+            }
+        }
+
+        if runners == 0 {
+            println!("No tests found; mark test functions with @test");
+            return Ok(());
+        }
+
+        if !failed.is_empty() {
+            let files: Vec<String> = failed.iter().map(|f| f.display().to_string()).collect();
+            anyhow::bail!("Tests failed in:\n  {}", files.join("\n  "));
+        }
+
+        println!(
+            "{} Ran tests from {} file{} in {:.2}s",
+            "Finished:".green(),
+            runners,
+            if runners == 1 { "" } else { "s" },
+            test_start.elapsed().as_secs_f64()
+        );
+        Ok(())
+    }
+
+    /// Returns the local files a source file includes with `@include "..."`,
+    /// resolved relative to the including file and then the include search
+    /// directories, as zedc does. Standard library includes are skipped since
+    /// they can't include project files.
+    fn local_includes(source_path: &Path, include_dirs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let source = fs::read_to_string(source_path)
+            .with_context(|| format!("Failed to read {}", source_path.display()))?;
+        let dir = source_path.parent().unwrap_or(Path::new("."));
+
+        let mut includes = Vec::new();
+        for line in source.lines() {
+            let Some(rest) = line.trim().strip_prefix("@include") else {
+                continue;
+            };
+            let mut parts = rest.split('"');
+            if let (Some(_), Some(path)) = (parts.next(), parts.next()) {
+                let relative = dir.join(path);
+                let include = if relative.exists() {
+                    relative
+                } else {
+                    include_dirs
+                        .iter()
+                        .map(|dir| dir.join(path))
+                        .find(|candidate| candidate.exists())
+                        .unwrap_or(relative)
+                };
+                includes.push(include.canonicalize().unwrap_or(include));
+            }
+        }
+        Ok(includes)
+    }
+
+    /// Checks the project's `@include` graph for cycles before compiling, so
+    /// a cycle is reported with every file involved instead of surfacing as
+    /// confusing per-file or link errors.
+    fn check_include_cycles(&self, files: &[PathBuf]) -> Result<()> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            Visiting,
+            Done,
+        }
+
+        fn visit(
+            root: &Path,
+            include_dirs: &[PathBuf],
+            file: &Path,
+            graph: &mut HashMap<PathBuf, Vec<PathBuf>>,
+            states: &mut HashMap<PathBuf, State>,
+            stack: &mut Vec<PathBuf>,
+        ) -> Result<()> {
+            match states.get(file) {
+                Some(State::Done) => return Ok(()),
+                Some(State::Visiting) => {
+                    let start = stack.iter().position(|f| f == file).unwrap_or(0);
+                    let cycle: Vec<String> = stack[start..]
+                        .iter()
+                        .chain(std::iter::once(&file.to_path_buf()))
+                        .map(|f| f.strip_prefix(root).unwrap_or(f).display().to_string())
+                        .collect();
+                    anyhow::bail!("Circular include detected:\n  {}", cycle.join("\n  -> "));
+                }
+                None => {}
+            }
+
+            if !graph.contains_key(file) {
+                let includes = if file.exists() {
+                    ZedProject::local_includes(file, include_dirs)?
+                } else {
+                    Vec::new()
+                };
+                graph.insert(file.to_path_buf(), includes);
+            }
+
+            states.insert(file.to_path_buf(), State::Visiting);
+            stack.push(file.to_path_buf());
+            for include in graph[file].clone() {
+                visit(root, include_dirs, &include, graph, states, stack)?;
+            }
+            stack.pop();
+            states.insert(file.to_path_buf(), State::Done);
+            Ok(())
+        }
+
+        let root = self.root.canonicalize().unwrap_or_else(|_| self.root.clone());
+        let include_dirs = self.include_dirs();
+        let mut graph = HashMap::new();
+        let mut states = HashMap::new();
+        for file in files {
+            let file = file.canonicalize().unwrap_or_else(|_| file.clone());
+            visit(&root, &include_dirs, &file, &mut graph, &mut states, &mut Vec::new())?;
+        }
         Ok(())
     }
 
-    fn compile_to_asm(&self, source: &Path, output: &Path) -> Result<Output> {
+    /// Compiles one file to assembly and assembles it, without printing
+    /// anything; `build_artifacts` reports the result once every file is done
+    fn compile_and_assemble(&self, source: &Path, asm_path: &Path, obj_path: &Path) -> Result<FileBuild> {
+        let lines = fs::read_to_string(source)
+            .with_context(|| format!("Failed to read {}", source.display()))?
+            .lines()
+            .count();
+
+        let phase_start = Instant::now();
+        let zedc = self.run_zedc(source, asm_path, false)?;
+        let compile_time = phase_start.elapsed();
+
+        let phase_start = Instant::now();
+        let assembled = if zedc.status.success() {
+            self.assemble(asm_path, obj_path).map(|_| ())
+        } else {
+            Ok(())
+        };
+        let assemble_time = phase_start.elapsed();
+
+        Ok(FileBuild {
+            lines,
+            zedc,
+            compile_time,
+            assembled,
+            assemble_time,
+        })
+    }
+
+    fn compile_to_asm(&self, source: &Path, output: &Path, test: bool) -> Result<Output> {
+        let output = self.run_zedc(source, output, test)?;
+        self.report_zedc(source, &output)?;
+        Ok(output)
+    }
+
+    /// Runs zedc on one file, leaving its diagnostics for `report_zedc`
+    fn run_zedc(&self, source: &Path, output: &Path, test: bool) -> Result<Output> {
         let stdlib_path = Self::get_stdlib_path()?;
 
-        let output = Command::new("zedc")
+        let mut command = Command::new("zedc");
+        command
             .arg(source)
             .arg("-o")
             .arg(output)
             .arg("--stdlib-path")
-            .arg(stdlib_path)
+            .arg(stdlib_path);
+        for dir in self.include_dirs() {
+            command.arg("-I").arg(dir);
+        }
+        if let Some(target) = &self.config.platform {
+            command.arg("--target").arg(target);
+        }
+        if self.verbose {
+            command.arg("--verbose");
+        }
+        if self.message_format == MessageFormat::Json {
+            command.arg("--error-format=json");
+        }
+        if test {
+            command.arg("--test");
+        }
+
+        command
             .output()
-            .context("Failed to execute zedc. Is it installed?")?;
+            .context("Failed to execute zedc. Is it installed?")
+    }
+
+    /// Prints zedc's diagnostics for one file, failing if it didn't compile
+    fn report_zedc(&self, source: &Path, output: &Output) -> Result<()> {
+        if self.message_format == MessageFormat::Json {
+            // zedc prints one JSON diagnostic per line; anything else on
+            // stderr is verbose tracing
+            for line in String::from_utf8_lossy(&output.stderr).lines() {
+                match serde_json::from_str::<serde_json::Value>(line) {
+                    Ok(diagnostic) if diagnostic.is_object() => self.event(
+                        "diagnostic",
+                        serde_json::json!({ "file": source, "diagnostic": diagnostic }),
+                    ),
+                    _ => eprintln!("{}", line),
+                }
+            }
+            if !output.status.success() {
+                anyhow::bail!("Compilation of {} failed", source.display());
+            }
+            return Ok(());
+        }
 
         if !output.status.success() {
             anyhow::bail!(
@@ -232,16 +831,22 @@ println("Hello from Zed!");
                 String::from_utf8_lossy(&output.stderr).red()
             );
         }
-        Ok(output)
+
+        // Show zedc's trace and any warnings
+        if self.verbose {
+            eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        }
+        Ok(())
     }
 
     fn assemble(&self, source: &Path, output: &Path) -> Result<Output> {
-        let output = Command::new("as")
+        let assembler = self.config.assembler.as_deref().unwrap_or("as");
+        let output = Command::new(assembler)
             .arg(source)
             .arg("-o")
             .arg(output)
             .output()
-            .context("Failed to execute as")?;
+            .with_context(|| format!("Failed to execute {}", assembler))?;
 
         if !output.status.success() {
             anyhow::bail!(
@@ -255,16 +860,24 @@ println("Hello from Zed!");
     fn link(&self, obj_dir: &Path, output: &Path) -> Result<Output> {
         let obj_files: Vec<_> = fs::read_dir(obj_dir)?
             .filter_map(|e| e.ok())
-            .filter(|e| e.path().extension().map_or(false, |ext| ext == "o"))
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "o"))
             .map(|e| e.path())
             .collect();
 
-        let output = Command::new("ld")
-            .args(&obj_files)
+        self.link_objects(&obj_files, output)
+    }
+
+    fn link_objects(&self, obj_files: &[PathBuf], output: &Path) -> Result<Output> {
+        // macOS executables need libSystem, which the C compiler driver links
+        let default_linker = if self.targets_macos() { "cc" } else { "ld" };
+        let linker = self.config.linker.as_deref().unwrap_or(default_linker);
+        let output = Command::new(linker)
+            .args(obj_files)
             .arg("-o")
             .arg(output)
+            .args(&self.config.linker_flags)
             .output()
-            .context("Failed to execute ld")?;
+            .with_context(|| format!("Failed to execute {}", linker))?;
 
         if !output.status.success() {
             anyhow::bail!(
@@ -275,8 +888,10 @@ println("Hello from Zed!");
         Ok(output)
     }
 
-    fn run(&self, release: bool) -> Result<()> {
-        self.build(release)?;
+    /// Builds the project and runs it with `args`. The program shares this
+    /// terminal, so its output appears as it's written.
+    fn run(&self, release: bool, force: bool, args: &[String]) -> Result<()> {
+        self.build(release, force)?;
 
         let target_dir = self.root.join("target");
         let build_type = if release { "release" } else { "debug" };
@@ -284,18 +899,70 @@ println("Hello from Zed!");
 
         println!("{} {}", "Running:".blue(), executable.display());
 
-        let output = Command::new(executable)
-            .output()
+        let status = Command::new(executable)
+            .args(args)
+            .status()
             .context("Failed to execute program")?;
 
-        println!("{}", String::from_utf8_lossy(&output.stdout));
+        if !status.success() {
+            anyhow::bail!("Program exited with error ({})", status);
+        }
+        Ok(())
+    }
+
+    fn doc(&self, open: bool, private: bool) -> Result<()> {
+        let doc_dir = self.root.join("target").join("doc");
+        fs::create_dir_all(&doc_dir)?;
+
+        println!("{} {}", "Documenting:".blue(), self.config.name);
+
+        let mut command = Command::new("zed-docgen");
+        command
+            .arg(self.root.join("src"))
+            .arg("-o")
+            .arg(&doc_dir)
+            .arg("--title")
+            .arg(&self.config.name);
+        if private {
+            command.arg("--private");
+        }
+
+        let output = command
+            .output()
+            .context("Failed to execute zed-docgen. Is it installed?")?;
 
         if !output.status.success() {
             anyhow::bail!(
-                "Program exited with error:\n{}",
-                String::from_utf8_lossy(&output.stderr)
+                "Documentation generation failed:\n{}",
+                String::from_utf8_lossy(&output.stderr).red()
             );
         }
+
+        let index = doc_dir.join("index.html");
+        println!("{} Generated {}", "Success:".green(), index.display());
+
+        if open {
+            println!("{} {}", "Opening:".blue(), index.display());
+            Self::open_in_browser(&index)?;
+        }
+        Ok(())
+    }
+
+    fn open_in_browser(path: &Path) -> Result<()> {
+        let mut command = if cfg!(target_os = "macos") {
+            Command::new("open")
+        } else if cfg!(target_os = "windows") {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        } else {
+            Command::new("xdg-open")
+        };
+
+        command
+            .arg(path)
+            .spawn()
+            .context("Failed to open documentation in a browser")?;
         Ok(())
     }
 
@@ -317,13 +984,36 @@ fn main() -> Result<()> {
             let project = ZedProject::new(&name)?;
             project.create()?;
         }
-        Commands::Build { release } => {
-            let project = ZedProject::load(&std::env::current_dir()?)?;
-            project.build(release)?;
+        Commands::Build {
+            release,
+            force,
+            message_format,
+            target,
+        } => {
+            let project = ZedProject::load(&std::env::current_dir()?)?
+                .with_verbose(cli.verbose)
+                .with_message_format(message_format)
+                .with_target(target)?;
+            project.build(release, force)?;
+        }
+        Commands::Run {
+            release,
+            force,
+            target,
+            args,
+        } => {
+            let project = ZedProject::load(&std::env::current_dir()?)?
+                .with_verbose(cli.verbose)
+                .with_target(target)?;
+            project.run(release, force, &args)?;
+        }
+        Commands::Test => {
+            let project = ZedProject::load(&std::env::current_dir()?)?.with_verbose(cli.verbose);
+            project.test()?;
         }
-        Commands::Run { release } => {
+        Commands::Doc { open, private } => {
             let project = ZedProject::load(&std::env::current_dir()?)?;
-            project.run(release)?;
+            project.doc(open, private)?;
         }
         Commands::Clean => {
             let project = ZedProject::load(&std::env::current_dir()?)?;
@@ -5,17 +5,33 @@ pub enum AstNode {
     Align(i64, Box<AstNode>),
     Variable(String),
     BinaryOp(Box<AstNode>, BinaryOperator, Box<AstNode>),
+    UnaryOp(UnaryOperator, Box<AstNode>),
     Assignment(String, Box<AstNode>),
     Block(Vec<AstNode>),
     If(Box<AstNode>, Box<AstNode>, Option<Box<AstNode>>),
+    Ternary(Box<AstNode>, Box<AstNode>, Box<AstNode>), // `cond ? a : b`
     While(Box<AstNode>, Box<AstNode>),
-    FunctionDecl(String, Vec<String>, Box<AstNode>),
+    DoWhile(Box<AstNode>, Box<AstNode>), // (body, condition)
+    For {
+        init: Option<Box<AstNode>>,
+        condition: Option<Box<AstNode>>,
+        update: Option<Box<AstNode>>,
+        body: Box<AstNode>,
+    },
+    FunctionDecl(String, Vec<String>, Box<AstNode>, FunctionAttributes),
     FunctionPredecl(String, Vec<String>),
     FunctionCall(String, Vec<AstNode>),
     Return(Option<Box<AstNode>>),
-    StringLiteral(String),
-    ArrayIndex(Box<AstNode>, Box<AstNode>),
-    ArrayAssignment(Box<AstNode>, Box<AstNode>, Box<AstNode>),
+    Break,
+    Continue,
+    StringLiteral(Vec<u8>),
+    ArrayIndex(Box<AstNode>, Box<AstNode>, usize), // (array, index, element size)
+    IndexCheck(Box<AstNode>, i64), // (index, dimension length), checked with debug assertions
+    ArrayAssignment(Box<AstNode>, Box<AstNode>, Box<AstNode>, usize),
+    AddressOf(Box<AstNode>), // of an ArrayIndex
+    Buffer(usize),           // bytes reserved in the frame; evaluates to their address
+    Assert(Box<AstNode>, String), // (condition, failure message)
+    ExprStmt(Box<AstNode>),       // expression evaluated only for its effects
     InlineAsm {
         template: String,
         outputs: Vec<(String, String)>, // (constraint, expression)
@@ -24,12 +40,111 @@ pub enum AstNode {
     },
 }
 
+impl AstNode {
+    // The nodes directly nested in this one, in source order
+    pub fn children(&self) -> Vec<&AstNode> {
+        match self {
+            AstNode::Number(_)
+            | AstNode::Variable(_)
+            | AstNode::StringLiteral(_)
+            | AstNode::FunctionPredecl(_, _)
+            | AstNode::Break
+            | AstNode::Continue
+            | AstNode::Buffer(_)
+            | AstNode::InlineAsm { .. } => Vec::new(),
+            AstNode::Align(_, node)
+            | AstNode::Assignment(_, node)
+            | AstNode::FunctionDecl(_, _, node, _)
+            | AstNode::Assert(node, _)
+            | AstNode::ExprStmt(node)
+            | AstNode::UnaryOp(_, node)
+            | AstNode::IndexCheck(node, _)
+            | AstNode::AddressOf(node) => vec![node],
+            AstNode::BinaryOp(left, _, right) => vec![left, right],
+            AstNode::Block(statements) => statements.iter().collect(),
+            AstNode::If(condition, then_branch, else_branch) => {
+                let mut children = vec![&**condition, &**then_branch];
+                children.extend(else_branch.as_deref());
+                children
+            }
+            AstNode::Ternary(condition, then_expr, else_expr) => {
+                vec![condition, then_expr, else_expr]
+            }
+            AstNode::While(condition, body) => vec![condition, body],
+            AstNode::DoWhile(body, condition) => vec![body, condition],
+            AstNode::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                let mut children: Vec<&AstNode> = Vec::new();
+                children.extend(init.as_deref());
+                children.extend(condition.as_deref());
+                children.extend(update.as_deref());
+                children.push(body);
+                children
+            }
+            AstNode::FunctionCall(_, args) => args.iter().collect(),
+            AstNode::Return(value) => value.as_deref().into_iter().collect(),
+            AstNode::ArrayIndex(array, index, _) => vec![array, index],
+            AstNode::ArrayAssignment(array, index, value, _) => vec![array, index, value],
+        }
+    }
+
+    // Calls `visit` on this node and everything nested in it, parents first.
+    // Passes use this instead of matching every variant themselves.
+    pub fn walk<'a>(&'a self, visit: &mut impl FnMut(&'a AstNode)) {
+        visit(self);
+        for child in self.children() {
+            child.walk(visit);
+        }
+    }
+}
+
+// Set by the `@...` attributes written before `fn`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FunctionAttributes {
+    pub cconv: CallingConvention,
+    // `@test`: run by the test runner that `zedc --test` generates
+    pub test: bool,
+}
+
+// How a function receives its arguments, chosen with `@cconv("name")`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CallingConvention {
+    // System V AMD64: the first six arguments in rdi, rsi, rdx, rcx, r8
+    // and r9, the rest on the stack
+    #[default]
+    SysV,
+}
+
+impl CallingConvention {
+    pub const ALL: [CallingConvention; 1] = [CallingConvention::SysV];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CallingConvention::SysV => "sysv",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<CallingConvention> {
+        Self::ALL.into_iter().find(|cconv| cconv.name() == name)
+    }
+}
+
 #[derive(Debug)]
 pub enum BinaryOperator {
     Add,
     Subtract,
     Multiply,
     Divide,
+    Modulo,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
     Equals,
     NotEquals,
     And,
@@ -39,3 +154,10 @@ pub enum BinaryOperator {
     LessEqual,
     GreaterEqual,
 }
+
+#[derive(Debug)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+    BitwiseNot,
+}
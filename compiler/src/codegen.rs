@@ -1,5 +1,150 @@
-use crate::ast::{AstNode, BinaryOperator};
+use crate::ast::{AstNode, BinaryOperator, CallingConvention, UnaryOperator};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// String literal indices for the output of a generated test runner
+struct TestRunner {
+    tests: Vec<(String, usize)>, // (function name, "test name ... " header)
+    ok: usize,
+    summary: usize,
+}
+
+// The operating system the generated assembly runs on
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Target {
+    #[default]
+    Linux,
+    MacOs,
+}
+
+// The system calls generated code makes itself
+#[derive(Clone, Copy)]
+enum Syscall {
+    Write,
+    Exit,
+}
+
+impl Target {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linux" => Some(Target::Linux),
+            "macos" => Some(Target::MacOs),
+            _ => None,
+        }
+    }
+
+    // The system the compiler itself is running on
+    pub fn host() -> Self {
+        if cfg!(target_os = "macos") {
+            Target::MacOs
+        } else {
+            Target::Linux
+        }
+    }
+
+    fn syscall_number(self, syscall: Syscall) -> i64 {
+        match (self, syscall) {
+            (Target::Linux, Syscall::Write) => 1,
+            (Target::Linux, Syscall::Exit) => 60,
+            // macOS numbers BSD system calls from 0x2000000
+            (Target::MacOs, Syscall::Write) => 0x2000004,
+            (Target::MacOs, Syscall::Exit) => 0x2000001,
+        }
+    }
+
+    // The symbol the program starts at: the linker's default entry on
+    // Linux, and the function libSystem calls on macOS
+    pub fn entry_symbol(self) -> &'static str {
+        match self {
+            Target::Linux => "_start",
+            Target::MacOs => "_main",
+        }
+    }
+
+    fn text_section(self) -> &'static str {
+        match self {
+            Target::Linux => ".section .text",
+            Target::MacOs => ".section __TEXT,__text",
+        }
+    }
+
+    fn read_only_section(self) -> &'static str {
+        match self {
+            Target::Linux => ".section .rodata",
+            Target::MacOs => ".section __TEXT,__const",
+        }
+    }
+
+    // Where a separately compiled file registers its `__init_N` code. dyld
+    // calls everything in `__mod_init_func` before `_main` by itself.
+    fn init_section(self) -> &'static str {
+        match self {
+            Target::Linux => ".section .init_array,\"aw\",@init_array",
+            Target::MacOs => ".section __DATA,__mod_init_func,mod_init_funcs",
+        }
+    }
+}
+
+// Whether a function named `name` would clash with a label the generator
+// emits itself: the entry point on any target, the `__init_N` code of
+// included files, or a string literal (`strN`, or `str_XXXXXXXX_N` once a
+// source name is set)
+pub fn is_reserved_label(name: &str) -> bool {
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+    if name == "_start"
+        || name == "_main"
+        || name.strip_prefix("__init_").is_some_and(is_number)
+    {
+        return true;
+    }
+    match name.strip_prefix("str") {
+        Some(rest) if is_number(rest) => true,
+        Some(rest) => rest
+            .strip_prefix('_')
+            .and_then(|rest| rest.split_once('_'))
+            .is_some_and(|(hash, index)| {
+                hash.len() == 8 && hash.bytes().all(|b| b.is_ascii_hexdigit()) && is_number(index)
+            }),
+        None => false,
+    }
+}
+
+fn fnv1a(text: &str) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in text.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+// Re-escapes a decoded string literal for a `.string` directive. Bytes
+// outside printable ASCII, including an embedded NUL, become three-digit
+// octal escapes so a following digit can't be read as part of them.
+fn escape_asm_string(s: &[u8]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for &byte in s {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\t' => escaped.push_str("\\t"),
+            b'\r' => escaped.push_str("\\r"),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped
+}
+
+// Stands in for a frame's `subq` until its size is known
+const FRAME_RESERVE_PLACEHOLDER: &str = "    subq $FRAME_SIZE, %rsp";
+
+// The global symbol a main file defines for each file it includes
+fn included_marker(path: &Path) -> String {
+    format!("__zed_included_{:08x}", fnv1a(&path.to_string_lossy()))
+}
 
 #[allow(dead_code)]
 pub struct CodeGenerator {
@@ -7,8 +152,19 @@ pub struct CodeGenerator {
     label_count: usize,
     var_map: HashMap<String, i32>,
     current_stack_offset: i32,
-    string_literals: Vec<String>,
+    string_literals: Vec<Vec<u8>>,
+    string_label_prefix: String,
     is_main_file: bool,
+    debug_assertions: bool,
+    test_mode: bool,
+    in_function: bool,
+    // (continue, break) labels of the enclosing loops, innermost last
+    loop_labels: Vec<(String, String)>,
+    unit_marker: String,
+    included_markers: Vec<String>,
+    // Bytes pushed since the current frame was set up
+    stack_depth: i64,
+    target: Target,
 }
 
 impl CodeGenerator {
@@ -19,21 +175,126 @@ impl CodeGenerator {
             var_map: HashMap::new(),
             current_stack_offset: 0,
             string_literals: Vec::new(),
+            string_label_prefix: "str".to_string(),
             is_main_file,
+            debug_assertions: false,
+            test_mode: false,
+            in_function: false,
+            loop_labels: Vec::new(),
+            unit_marker: String::new(),
+            included_markers: Vec::new(),
+            stack_depth: 0,
+            target: Target::default(),
         }
     }
 
+    pub fn set_target(&mut self, target: Target) {
+        self.target = target;
+    }
+
+    // Makes `_start` run the file's `@test` functions instead of its
+    // top-level code
+    pub fn set_test_mode(&mut self, enabled: bool) {
+        self.test_mode = enabled;
+    }
+
+    // Enables runtime checks such as array index validation
+    pub fn set_debug_assertions(&mut self, enabled: bool) {
+        self.debug_assertions = enabled;
+    }
+
+    // Names string literal labels after the source file, so that labels from
+    // separately compiled files never collide when linked together
+    pub fn set_source_name(&mut self, name: &str) {
+        self.string_label_prefix = format!("str_{:08x}_", fnv1a(name));
+    }
+
+    // The canonical path of the file being compiled, which names the marker
+    // its top-level code checks before running from `.init_array`
+    pub fn set_unit_path(&mut self, path: &Path) {
+        self.unit_marker = included_marker(path);
+    }
+
+    // Files whose top-level code the main file inlines through `@include`.
+    // `_start` marks them so their own `__init_N` code doesn't run it again.
+    pub fn set_included_files(&mut self, paths: &[PathBuf]) {
+        self.included_markers = paths.iter().map(|path| included_marker(path)).collect();
+    }
+
+    fn string_label(&self, index: usize) -> String {
+        format!("{}{}", self.string_label_prefix, index)
+    }
+
     fn emit(&mut self, line: &str) {
+        self.track_stack_depth(line);
         self.assembly.push_str(line);
         self.assembly.push('\n');
     }
 
+    // Keeps `stack_depth` in step with the instructions that move %rsp.
+    // Every frame starts 16-byte aligned, so %rsp is aligned wherever
+    // `stack_depth` is a multiple of 16.
+    fn track_stack_depth(&mut self, line: &str) {
+        let mut parts = line.split_whitespace();
+        let adjustment = match (parts.next(), parts.next(), parts.next()) {
+            (Some("pushq"), Some(_), _) => 8,
+            (Some("popq"), Some(_), _) => -8,
+            (Some(op @ ("addq" | "subq")), Some(amount), Some("%rsp")) => {
+                let Some(bytes) = amount
+                    .strip_prefix('$')
+                    .and_then(|amount| amount.trim_end_matches(',').parse::<i64>().ok())
+                else {
+                    return;
+                };
+                if op == "addq" {
+                    -bytes
+                } else {
+                    bytes
+                }
+            }
+            _ => return,
+        };
+        self.stack_depth += adjustment;
+    }
+
+    // Emits a call without stack-passed arguments. System V requires %rsp to
+    // be 16-byte aligned at every call, so pad it if pushes have left it off.
+    fn emit_aligned_call(&mut self, target: &str) {
+        let padded = self.stack_depth % 16 != 0;
+        if padded {
+            self.emit("    subq $8, %rsp        # align the stack for the call");
+        }
+        self.emit(&format!("    call {}", target));
+        if padded {
+            self.emit("    addq $8, %rsp");
+        }
+    }
+
     fn get_new_label(&mut self) -> String {
         let label = format!(".L{}", self.label_count);
         self.label_count += 1;
         label
     }
 
+    // Emits the instruction that reserves a frame's locals. Buffers can grow
+    // the frame anywhere in the body, so the size is filled in afterwards by
+    // `patch_frame_reserve`.
+    fn emit_frame_reserve(&mut self) -> usize {
+        let position = self.assembly.len();
+        self.emit(FRAME_RESERVE_PLACEHOLDER);
+        position
+    }
+
+    // Sizes the frame reserved at `position` to cover every local allocated
+    // since, rounded up to keep %rsp 16-byte aligned
+    fn patch_frame_reserve(&mut self, position: usize) {
+        let size = (-self.current_stack_offset + 15) & !15;
+        self.assembly.replace_range(
+            position..position + FRAME_RESERVE_PLACEHOLDER.len(),
+            &format!("    subq ${}, %rsp", size),
+        );
+    }
+
     fn get_var_location(&mut self, name: &str) -> i32 {
         if let Some(&offset) = self.var_map.get(name) {
             offset
@@ -45,52 +306,182 @@ impl CodeGenerator {
         }
     }
 
-    fn add_string_literal(&mut self, s: &str) -> usize {
+    // Interns a string literal, returning the index of its `strN` label.
+    // Identical literals share a single entry in the data section.
+    fn add_string_literal(&mut self, s: &[u8]) -> usize {
+        if let Some(index) = self.string_literals.iter().position(|x| x == s) {
+            return index;
+        }
+
         let index = self.string_literals.len();
-        self.string_literals.push(s.to_string());
+        self.string_literals.push(s.to_vec());
         index
     }
 
     fn collect_string_literals(&mut self, node: &AstNode) {
-        match node {
+        node.walk(&mut |node| match node {
             AstNode::StringLiteral(s) => {
                 self.add_string_literal(s);
             }
-            AstNode::Block(statements) => {
-                for stmt in statements {
-                    self.collect_string_literals(stmt);
-                }
-            }
-            AstNode::If(cond, then_branch, else_branch) => {
-                self.collect_string_literals(cond);
-                self.collect_string_literals(then_branch);
-                if let Some(else_node) = else_branch {
-                    self.collect_string_literals(else_node);
-                }
+            AstNode::Assert(_, message) => {
+                self.add_string_literal(message.as_bytes());
             }
-            AstNode::While(cond, body) => {
-                self.collect_string_literals(cond);
-                self.collect_string_literals(body);
-            }
-            AstNode::FunctionDecl(_, _, body) => {
-                self.collect_string_literals(body);
-            }
-            AstNode::BinaryOp(left, _, right) => {
-                self.collect_string_literals(left);
-                self.collect_string_literals(right);
-            }
-            AstNode::Assignment(_, value) => {
-                self.collect_string_literals(value);
+            _ => {}
+        });
+    }
+
+    // Splits an inline assembly template into the lines to emit.
+    //
+    // The template is split on newlines, whether written literally or with
+    // `\n` escapes, so `"nop\nnop"` yields two instructions. Trailing
+    // whitespace is dropped, and the first line (which follows the opening
+    // quote) loses its leading whitespace. The remaining lines are dedented
+    // by their smallest common indentation, so relative indentation such as
+    // labels written flush against instructions is preserved.
+    fn asm_template_lines(template: &str) -> Vec<String> {
+        let mut lines = template.lines().map(str::trim_end);
+        let first = lines.next().unwrap_or("").trim_start();
+        let rest: Vec<&str> = lines.collect();
+
+        let common_indent = rest
+            .iter()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+
+        let mut result = Vec::new();
+        if !first.is_empty() {
+            result.push(first.to_string());
+        }
+        for line in rest {
+            if line.is_empty() {
+                result.push(String::new());
+            } else {
+                result.push(line[common_indent..].to_string());
             }
-            AstNode::FunctionCall(_, args) => {
-                for arg in args {
-                    self.collect_string_literals(arg);
+        }
+        result
+    }
+
+    fn emit_syscall_number(&mut self, syscall: Syscall) {
+        let number = self.target.syscall_number(syscall);
+        self.emit(&format!("    movq ${}, %rax", number));
+    }
+
+    // Writes the interned string literal `index` to a file descriptor
+    fn emit_write(&mut self, fd: i32, index: usize) {
+        let label = self.string_label(index);
+        let len = self.string_literals[index].len();
+        self.emit_syscall_number(Syscall::Write);
+        self.emit(&format!("    movq ${}, %rdi", fd));
+        self.emit(&format!("    leaq {}(%rip), %rsi", label));
+        self.emit(&format!("    movq ${}, %rdx", len));
+        self.emit("    syscall");
+    }
+
+    fn emit_write_newline(&mut self, fd: i32) {
+        self.emit("    pushq $10");
+        self.emit_syscall_number(Syscall::Write);
+        self.emit(&format!("    movq ${}, %rdi", fd));
+        self.emit("    movq %rsp, %rsi");
+        self.emit("    movq $1, %rdx");
+        self.emit("    syscall");
+        self.emit("    addq $8, %rsp");
+    }
+
+    // Finds the `@test` functions and interns the runner's output strings,
+    // which have to be known before the data section is emitted
+    fn prepare_test_runner(&mut self, ast: &[AstNode]) -> TestRunner {
+        let mut tests = Vec::new();
+        for node in ast {
+            if let AstNode::FunctionDecl(name, _, _, attributes) = node {
+                if attributes.test {
+                    let header = self.add_string_literal(format!("test {} ... ", name).as_bytes());
+                    tests.push((name.clone(), header));
                 }
             }
-            _ => {}
+        }
+
+        TestRunner {
+            ok: self.add_string_literal(b"ok"),
+            summary: self.add_string_literal(format!("test result: ok. {} passed", tests.len()).as_bytes()),
+            tests,
         }
     }
 
+    // The body of `_start` in test mode: runs each test in order, printing
+    // its name before and "ok" after. A failing assert exits the process
+    // with status 1, so the last name printed is the test that failed.
+    fn generate_test_runner(&mut self, runner: &TestRunner) {
+        for (name, header) in &runner.tests {
+            self.emit_write(1, *header);
+            self.emit_aligned_call(name);
+            self.emit_write(1, runner.ok);
+            self.emit_write_newline(1);
+        }
+
+        self.emit_write_newline(1);
+        self.emit_write(1, runner.summary);
+        self.emit_write_newline(1);
+    }
+
+    // Traps if the array index in %rcx is negative. Each index into an
+    // `array` declaration is also checked against its dimension, by an
+    // `IndexCheck`, but other arrays don't carry a length, so a negative
+    // index is all that can be detected for them.
+    fn emit_index_check(&mut self) {
+        if !self.debug_assertions {
+            return;
+        }
+
+        let ok_label = self.get_new_label();
+        self.emit("    testq %rcx, %rcx");
+        self.emit(&format!("    jns {}", ok_label));
+        self.emit("    ud2              # index out of bounds");
+        self.emit(&format!("{}:", ok_label));
+    }
+
+    // Calls the `__init_N` code of every separately compiled file, which the
+    // linker gathers between `__init_array_start` and `__init_array_end`
+    fn emit_init_calls(&mut self) {
+        let loop_label = self.get_new_label();
+        let done_label = self.get_new_label();
+
+        self.emit("    leaq __init_array_start(%rip), %rbx");
+        self.emit(&format!("{}:", loop_label));
+        self.emit("    leaq __init_array_end(%rip), %rax");
+        self.emit("    cmpq %rax, %rbx");
+        self.emit(&format!("    jae {}", done_label));
+        self.emit("    pushq %rbx");
+        self.emit_aligned_call("*(%rbx)");
+        self.emit("    popq %rbx");
+        self.emit("    addq $8, %rbx");
+        self.emit(&format!("    jmp {}", loop_label));
+        self.emit(&format!("{}:", done_label));
+    }
+
+    // Generates a loop body in which `continue` jumps to `continue_label` and
+    // `break` to `end_label`
+    fn generate_loop_body(&mut self, body: &AstNode, continue_label: &str, end_label: &str) {
+        self.loop_labels.push((continue_label.to_string(), end_label.to_string()));
+        self.generate_node(body);
+        self.loop_labels.pop();
+    }
+
+    // Leaves the address of element `index` of `array` in %rax
+    fn emit_element_address(&mut self, array: &AstNode, index: &AstNode, element_size: usize) {
+        // Generate array base address
+        self.generate_node(array);
+        // Generate index
+        self.generate_node(index);
+
+        self.emit("    popq %rcx        # index");
+        self.emit("    popq %rax        # array base");
+        self.emit_index_check();
+        self.emit(&format!("    leaq (%rax,%rcx,{}), %rax", element_size));
+    }
+
     fn align_stack(&mut self, alignment: i64) {
         // Save original stack pointer
         self.emit("    movq %rsp, %rax");
@@ -107,10 +498,50 @@ impl CodeGenerator {
         self.emit("    popq %rsp");
     }
 
+    // Compares the NUL-terminated strings whose addresses are on top of the
+    // stack, pushing 1 if their contents are equal and 0 otherwise
+    fn generate_string_comparison(&mut self, negate: bool) {
+        let loop_label = self.get_new_label();
+        let equal_label = self.get_new_label();
+        let not_equal_label = self.get_new_label();
+        let end_label = self.get_new_label();
+
+        self.emit("    popq %rcx");
+        self.emit("    popq %rax");
+
+        self.emit(&format!("{}:", loop_label));
+        self.emit("    movb (%rax), %dl");
+        self.emit("    cmpb (%rcx), %dl");
+        self.emit(&format!("    jne {}", not_equal_label));
+        self.emit("    testb %dl, %dl"); // Both strings ended together
+        self.emit(&format!("    je {}", equal_label));
+        self.emit("    incq %rax");
+        self.emit("    incq %rcx");
+        self.emit(&format!("    jmp {}", loop_label));
+
+        self.emit(&format!("{}:", equal_label));
+        self.emit("    movq $1, %rax");
+        self.emit(&format!("    jmp {}", end_label));
+        self.emit(&format!("{}:", not_equal_label));
+        self.emit("    xorq %rax, %rax");
+        self.emit(&format!("{}:", end_label));
+
+        if negate {
+            self.emit("    xorq $1, %rax");
+        }
+        self.emit("    pushq %rax");
+    }
+
     fn generate_node(&mut self, node: &AstNode) {
         match node {
             AstNode::Number(n) => {
-                self.emit(&format!("    pushq ${}", n));
+                // pushq only takes a sign-extended 32-bit immediate
+                if i32::try_from(*n).is_ok() {
+                    self.emit(&format!("    pushq ${}", n));
+                } else {
+                    self.emit(&format!("    movabsq ${}, %rax", n));
+                    self.emit("    pushq %rax");
+                }
             }
             AstNode::Align(alignment, node) => {
                 self.align_stack(*alignment);
@@ -122,12 +553,9 @@ impl CodeGenerator {
                 self.emit(&format!("    pushq {}(%rbp)", offset));
             }
             AstNode::StringLiteral(s) => {
-                let index = if let Some(idx) = self.string_literals.iter().position(|x| x == s) {
-                    idx
-                } else {
-                    self.add_string_literal(s)
-                };
-                self.emit(&format!("    leaq str{}(%rip), %rax", index));
+                let index = self.add_string_literal(s);
+                let label = self.string_label(index);
+                self.emit(&format!("    leaq {}(%rip), %rax", label));
                 self.emit("    pushq %rax");
             }
             AstNode::Assignment(name, value) => {
@@ -136,6 +564,43 @@ impl CodeGenerator {
                 self.emit("    popq %rax");
                 self.emit(&format!("    movq %rax, {}(%rbp)", offset));
             }
+            AstNode::BinaryOp(
+                left,
+                op @ (BinaryOperator::Equals | BinaryOperator::NotEquals),
+                right,
+            ) if matches!(**left, AstNode::StringLiteral(_))
+                || matches!(**right, AstNode::StringLiteral(_)) =>
+            {
+                // Comparing against a string literal compares contents,
+                // not addresses
+                self.generate_node(left);
+                self.generate_node(right);
+                self.generate_string_comparison(matches!(op, BinaryOperator::NotEquals));
+            }
+            AstNode::BinaryOp(left, op @ (BinaryOperator::And | BinaryOperator::Or), right) => {
+                // The right operand is only evaluated when the left one
+                // doesn't already decide the result
+                let end_label = self.get_new_label();
+
+                self.generate_node(left);
+                self.emit("    popq %rax");
+                self.emit("    testq %rax, %rax");
+                self.emit("    setne %al");
+                self.emit("    movzbq %al, %rax");
+                match op {
+                    BinaryOperator::And => self.emit(&format!("    jz {}", end_label)),
+                    _ => self.emit(&format!("    jnz {}", end_label)),
+                }
+
+                self.generate_node(right);
+                self.emit("    popq %rax");
+                self.emit("    testq %rax, %rax");
+                self.emit("    setne %al");
+                self.emit("    movzbq %al, %rax");
+
+                self.emit(&format!("{}:", end_label));
+                self.emit("    pushq %rax");
+            }
             AstNode::BinaryOp(left, op, right) => {
                 self.generate_node(left);
                 self.generate_node(right);
@@ -151,6 +616,17 @@ impl CodeGenerator {
                         self.emit("    cqo"); // Sign extend RAX into RDX
                         self.emit("    idivq %rcx");
                     }
+                    BinaryOperator::Modulo => {
+                        self.emit("    cqo"); // Sign extend RAX into RDX
+                        self.emit("    idivq %rcx");
+                        self.emit("    movq %rdx, %rax"); // The remainder is left in RDX
+                    }
+                    BinaryOperator::BitAnd => self.emit("    andq %rcx, %rax"),
+                    BinaryOperator::BitOr => self.emit("    orq %rcx, %rax"),
+                    BinaryOperator::BitXor => self.emit("    xorq %rcx, %rax"),
+                    // Shift counts must be in %cl
+                    BinaryOperator::ShiftLeft => self.emit("    salq %cl, %rax"),
+                    BinaryOperator::ShiftRight => self.emit("    sarq %cl, %rax"),
                     BinaryOperator::Equals => {
                         self.emit("    cmpq %rcx, %rax");
                         self.emit("    sete %al");
@@ -181,26 +657,25 @@ impl CodeGenerator {
                         self.emit("    setge %al");
                         self.emit("    movzbq %al, %rax");
                     }
-                    BinaryOperator::And => {
-                        self.emit("    testq %rax, %rax"); // Test first operand
-                        let skip_label = self.get_new_label();
-                        self.emit(&format!("    jz {}", skip_label)); // If false, skip second operand
-                        self.emit("    testq %rcx, %rcx"); // Test second operand
-                        self.emit("    setne %al"); // Set result based on second operand
-                        self.emit("    movzbq %al, %rax");
-                        self.emit(&format!("{}:", skip_label));
+                    BinaryOperator::And | BinaryOperator::Or => {
+                        unreachable!("logical operators are generated above")
                     }
-                    BinaryOperator::Or => {
-                        self.emit("    testq %rax, %rax"); // Test first operand
-                        let skip_label = self.get_new_label();
-                        self.emit(&format!("    jnz {}", skip_label)); // If true, skip second operand
-                        self.emit("    testq %rcx, %rcx"); // Test second operand
-                        self.emit("    setne %al"); // Set result based on second operand
+                }
+
+                self.emit("    pushq %rax");
+            }
+            AstNode::UnaryOp(op, operand) => {
+                self.generate_node(operand);
+                self.emit("    popq %rax");
+                match op {
+                    UnaryOperator::Negate => self.emit("    negq %rax"),
+                    UnaryOperator::Not => {
+                        self.emit("    testq %rax, %rax");
+                        self.emit("    sete %al");
                         self.emit("    movzbq %al, %rax");
-                        self.emit(&format!("{}:", skip_label));
                     }
+                    UnaryOperator::BitwiseNot => self.emit("    notq %rax"),
                 }
-
                 self.emit("    pushq %rax");
             }
             AstNode::If(condition, then_branch, else_branch) => {
@@ -222,6 +697,27 @@ impl CodeGenerator {
 
                 self.emit(&format!("{}:", end_label));
             }
+            AstNode::Ternary(condition, then_expr, else_expr) => {
+                let else_label = self.get_new_label();
+                let end_label = self.get_new_label();
+
+                self.generate_node(condition);
+                self.emit("    popq %rax");
+                self.emit("    testq %rax, %rax");
+                self.emit(&format!("    je {}", else_label));
+
+                // Each branch pushes one value, so the else branch starts
+                // from the depth the then branch did
+                let depth = self.stack_depth;
+                self.generate_node(then_expr);
+                self.emit(&format!("    jmp {}", end_label));
+
+                self.emit(&format!("{}:", else_label));
+                self.stack_depth = depth;
+                self.generate_node(else_expr);
+
+                self.emit(&format!("{}:", end_label));
+            }
             AstNode::While(condition, body) => {
                 let start_label = self.get_new_label();
                 let end_label = self.get_new_label();
@@ -233,51 +729,107 @@ impl CodeGenerator {
                 self.emit("    testq %rax, %rax");
                 self.emit(&format!("    je {}", end_label));
 
-                self.generate_node(body);
+                self.generate_loop_body(body, &start_label, &end_label);
+                self.emit(&format!("    jmp {}", start_label));
+
+                self.emit(&format!("{}:", end_label));
+            }
+            AstNode::DoWhile(body, condition) => {
+                // The condition is tested after the body, so a single
+                // conditional jump back is all the loop needs
+                let start_label = self.get_new_label();
+                let condition_label = self.get_new_label();
+                let end_label = self.get_new_label();
+
+                self.emit(&format!("{}:", start_label));
+                self.generate_loop_body(body, &condition_label, &end_label);
+
+                self.emit(&format!("{}:", condition_label));
+                self.generate_node(condition);
+                self.emit("    popq %rax");
+                self.emit("    testq %rax, %rax");
+                self.emit(&format!("    jne {}", start_label));
+                self.emit(&format!("{}:", end_label));
+            }
+            AstNode::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                let start_label = self.get_new_label();
+                let update_label = self.get_new_label();
+                let end_label = self.get_new_label();
+
+                if let Some(init) = init {
+                    self.generate_node(init);
+                }
+
+                self.emit(&format!("{}:", start_label));
+
+                if let Some(condition) = condition {
+                    self.generate_node(condition);
+                    self.emit("    popq %rax");
+                    self.emit("    testq %rax, %rax");
+                    self.emit(&format!("    je {}", end_label));
+                }
+
+                self.generate_loop_body(body, &update_label, &end_label);
+
+                // The update runs on every path back to the condition,
+                // including `continue`
+                self.emit(&format!("{}:", update_label));
+                if let Some(update) = update {
+                    self.generate_node(update);
+                }
                 self.emit(&format!("    jmp {}", start_label));
 
                 self.emit(&format!("{}:", end_label));
             }
+            AstNode::Break => {
+                let (_, end_label) =
+                    self.loop_labels.last().expect("parser rejects break outside loops");
+                self.emit(&format!("    jmp {}", end_label));
+            }
+            AstNode::Continue => {
+                let (continue_label, _) =
+                    self.loop_labels.last().expect("parser rejects continue outside loops");
+                self.emit(&format!("    jmp {}", continue_label));
+            }
             AstNode::Block(statements) => {
                 for stmt in statements {
                     self.generate_node(stmt);
                 }
             }
-            AstNode::FunctionDecl(name, params, body) => {
+            AstNode::FunctionDecl(name, params, body, attributes) => {
                 // Save old state
                 let old_var_map = self.var_map.clone();
                 let old_stack_offset = self.current_stack_offset;
+                let old_stack_depth = self.stack_depth;
 
                 // Reset state for new function
                 self.var_map.clear();
                 self.current_stack_offset = 0;
 
-                // Function prologue
+                // Function prologue. The call left %rsp 8 bytes short of
+                // 16-byte alignment; pushing %rbp and reserving a multiple
+                // of 16 bytes aligns it again.
                 self.emit(&format!("{}:", name));
                 self.emit("    pushq %rbp");
                 self.emit("    movq %rsp, %rbp");
-                self.emit("    subq $256, %rsp"); // Reserve stack space
+                let frame_reserve = self.emit_frame_reserve();
+                self.stack_depth = 0;
 
                 // Store parameters in stack
-                for (i, param) in params.iter().enumerate() {
-                    let offset = self.get_var_location(param);
-                    match i {
-                        0 => self.emit(&format!("    movq %rdi, {}(%rbp)", offset)),
-                        1 => self.emit(&format!("    movq %rsi, {}(%rbp)", offset)),
-                        2 => self.emit(&format!("    movq %rdx, {}(%rbp)", offset)),
-                        3 => self.emit(&format!("    movq %rcx, {}(%rbp)", offset)),
-                        4 => self.emit(&format!("    movq %r8, {}(%rbp)", offset)),
-                        5 => self.emit(&format!("    movq %r9, {}(%rbp)", offset)),
-                        _ => {
-                            let stack_param_offset = (i - 6 + 2) * 8;
-                            self.emit(&format!("    movq {}(%rbp), %rax", stack_param_offset));
-                            self.emit(&format!("    movq %rax, {}(%rbp)", offset));
-                        }
-                    }
+                match attributes.cconv {
+                    CallingConvention::SysV => self.store_sysv_params(params),
                 }
 
                 // Generate function body
+                let was_in_function = std::mem::replace(&mut self.in_function, true);
                 self.generate_node(body);
+                self.in_function = was_in_function;
+                self.patch_frame_reserve(frame_reserve);
 
                 // Function epilogue
                 self.emit("    movq %rbp, %rsp");
@@ -287,11 +839,20 @@ impl CodeGenerator {
                 // Restore old state
                 self.var_map = old_var_map;
                 self.current_stack_offset = old_stack_offset;
+                self.stack_depth = old_stack_depth;
             }
             AstNode::FunctionPredecl(_, _) => {
                 // Nothing to generate for predeclarations
             }
             AstNode::FunctionCall(name, args) => {
+                // Arguments past the sixth stay on the stack, so any padding
+                // for alignment has to go below them, before they're pushed
+                let stack_args = args.len().saturating_sub(6) as i64;
+                let padded = (self.stack_depth + stack_args * 8) % 16 != 0;
+                if padded {
+                    self.emit("    subq $8, %rsp        # align the stack for the call");
+                }
+
                 // Push arguments in reverse order
                 for arg in args.iter().rev() {
                     self.generate_node(arg);
@@ -310,48 +871,119 @@ impl CodeGenerator {
                     }
                 }
 
-                // Call function and push return value
+                // Call function, drop the stack-passed arguments and any
+                // padding, and push the return value
                 self.emit(&format!("    call {}", name));
+                let cleanup = stack_args * 8 + if padded { 8 } else { 0 };
+                if cleanup > 0 {
+                    self.emit(&format!("    addq ${}, %rsp", cleanup));
+                }
                 self.emit("    pushq %rax");
             }
+            AstNode::ExprStmt(expr) => {
+                self.generate_node(expr);
+
+                // Discard the result; assignments don't leave one
+                if !matches!(**expr, AstNode::Assignment(..) | AstNode::ArrayAssignment(..)) {
+                    self.emit("    addq $8, %rsp");
+                }
+            }
+            AstNode::Assert(condition, message) => {
+                let ok_label = self.get_new_label();
+                let index = self.add_string_literal(message.as_bytes());
+
+                self.generate_node(condition);
+                self.emit("    popq %rax");
+                self.emit("    testq %rax, %rax");
+                self.emit(&format!("    jnz {}", ok_label));
+
+                // Write the message and a newline to stderr, then exit(1)
+                self.emit_write(2, index);
+                self.emit_write_newline(2);
+                self.emit_syscall_number(Syscall::Exit);
+                self.emit("    movq $1, %rdi");
+                self.emit("    syscall");
+
+                self.emit(&format!("{}:", ok_label));
+            }
+            // A top-level return in the main program exits with its value
+            AstNode::Return(value) if self.is_main_file && !self.in_function => {
+                match value {
+                    Some(expr) => {
+                        self.generate_node(expr);
+                        self.emit("    popq %rdi");
+                    }
+                    None => self.emit("    xorq %rdi, %rdi"),
+                }
+                self.emit_syscall_number(Syscall::Exit);
+                self.emit("    syscall");
+            }
             AstNode::Return(value) => {
                 if let Some(expr) = value {
                     self.generate_node(expr);
                     self.emit("    popq %rax");
                 }
 
+                // Code after the return is still laid out for this depth
+                let stack_depth = self.stack_depth;
                 self.emit("    movq %rbp, %rsp");
                 self.emit("    popq %rbp");
                 self.emit("    ret");
+                self.stack_depth = stack_depth;
             }
-            AstNode::ArrayIndex(array, index) => {
-                // Generate array base address
-                self.generate_node(array);
-                // Generate index
-                self.generate_node(index);
+            AstNode::ArrayIndex(array, index, element_size) => {
+                self.emit_element_address(array, index, *element_size);
 
-                // Compute effective address and load value
-                self.emit("    popq %rcx        # index");
-                self.emit("    popq %rax        # array base");
-                self.emit("    leaq (%rax,%rcx), %rax");
-                self.emit("    movzbq (%rax), %rax");
+                // Narrower elements are zero-extended
+                match element_size {
+                    1 => self.emit("    movzbq (%rax), %rax"),
+                    2 => self.emit("    movzwq (%rax), %rax"),
+                    4 => self.emit("    movl (%rax), %eax"),
+                    _ => self.emit("    movq (%rax), %rax"),
+                }
                 self.emit("    pushq %rax");
             }
 
-            AstNode::ArrayAssignment(array, index, value) => {
+            AstNode::ArrayAssignment(array, index, value, element_size) => {
                 // Generate value first (will be on top of stack)
                 self.generate_node(value);
-                // Generate array base address
-                self.generate_node(array);
-                // Generate index
-                self.generate_node(index);
+                self.emit_element_address(array, index, *element_size);
 
                 // Store value at computed address
-                self.emit("    popq %rcx        # index");
-                self.emit("    popq %rax        # array base");
                 self.emit("    popq %rdx        # value");
-                self.emit("    movb %dl, (%rax,%rcx)");
+                match element_size {
+                    1 => self.emit("    movb %dl, (%rax)"),
+                    2 => self.emit("    movw %dx, (%rax)"),
+                    4 => self.emit("    movl %edx, (%rax)"),
+                    _ => self.emit("    movq %rdx, (%rax)"),
+                }
+            }
+            AstNode::Buffer(size) => {
+                // Keep whatever follows the buffer 8-byte aligned
+                self.current_stack_offset -= ((*size + 7) & !7) as i32;
+                self.emit(&format!("    leaq {}(%rbp), %rax", self.current_stack_offset));
+                self.emit("    pushq %rax");
+            }
+            AstNode::IndexCheck(index, length) => {
+                self.generate_node(index);
+                if self.debug_assertions {
+                    // Unsigned, so a negative index fails the check too
+                    let ok_label = self.get_new_label();
+                    self.emit("    movq (%rsp), %rcx");
+                    self.emit(&format!("    movq ${}, %rax", length));
+                    self.emit("    cmpq %rax, %rcx");
+                    self.emit(&format!("    jb {}", ok_label));
+                    self.emit("    ud2              # index out of bounds");
+                    self.emit(&format!("{}:", ok_label));
+                }
             }
+            AstNode::AddressOf(element) => match &**element {
+                AstNode::ArrayIndex(array, index, element_size) => {
+                    self.emit_element_address(array, index, *element_size);
+                    self.emit("    pushq %rax");
+                }
+                _ => unreachable!("the parser only allows '&' on array elements"),
+            },
             AstNode::InlineAsm {
                 template,
                 outputs,
@@ -382,8 +1014,12 @@ impl CodeGenerator {
                 }
 
                 // Emit the actual assembly template
-                for line in template.lines() {
-                    self.emit(&format!("    {}", line.trim()));
+                for line in Self::asm_template_lines(template) {
+                    if line.is_empty() {
+                        self.emit("");
+                    } else {
+                        self.emit(&format!("    {}", line));
+                    }
                 }
 
                 // Store output operands
@@ -411,6 +1047,27 @@ impl CodeGenerator {
         }
     }
 
+    // Copies arguments from the System V argument registers, and from the
+    // caller's frame past the sixth, into the function's stack slots
+    fn store_sysv_params(&mut self, params: &[String]) {
+        for (i, param) in params.iter().enumerate() {
+            let offset = self.get_var_location(param);
+            match i {
+                0 => self.emit(&format!("    movq %rdi, {}(%rbp)", offset)),
+                1 => self.emit(&format!("    movq %rsi, {}(%rbp)", offset)),
+                2 => self.emit(&format!("    movq %rdx, {}(%rbp)", offset)),
+                3 => self.emit(&format!("    movq %rcx, {}(%rbp)", offset)),
+                4 => self.emit(&format!("    movq %r8, {}(%rbp)", offset)),
+                5 => self.emit(&format!("    movq %r9, {}(%rbp)", offset)),
+                _ => {
+                    let stack_param_offset = (i - 6 + 2) * 8;
+                    self.emit(&format!("    movq {}(%rbp), %rax", stack_param_offset));
+                    self.emit(&format!("    movq %rax, {}(%rbp)", offset));
+                }
+            }
+        }
+    }
+
     pub fn generate(&mut self, ast: &[AstNode]) -> String {
         self.assembly.clear();
         self.string_literals.clear();
@@ -419,17 +1076,23 @@ impl CodeGenerator {
         for node in ast {
             self.collect_string_literals(node);
         }
+        let test_runner = (self.test_mode && self.is_main_file).then(|| self.prepare_test_runner(ast));
 
-        // Data section (only if we have string literals)
+        // String literals are constants, so they go in read-only data
         if !self.string_literals.is_empty() {
-            self.emit(".section .data");
+            self.emit(self.target.read_only_section());
 
             // Pre-format string declarations
             let string_declarations: Vec<String> = self
                 .string_literals
                 .iter()
                 .enumerate()
-                .flat_map(|(i, s)| vec![format!("str{}:", i), format!("    .string \"{}\"", s)])
+                .flat_map(|(i, s)| {
+                    vec![
+                        format!("{}:", self.string_label(i)),
+                        format!("    .string \"{}\"", escape_asm_string(s)),
+                    ]
+                })
                 .collect();
 
             // Emit string declarations
@@ -440,72 +1103,140 @@ impl CodeGenerator {
 
         // Text section
         self.emit("");
-        self.emit(".section .text");
+        self.emit(self.target.text_section());
 
         // Generate all functions first
         for node in ast {
-            if let AstNode::FunctionDecl(_, _, _) = node {
+            if let AstNode::FunctionDecl(_, _, _, _) = node {
                 self.generate_node(node);
             }
         }
 
-        // For main file, generate _start
+        // For main file, generate the entry point
         if self.is_main_file {
             // Main program
+            let entry = self.target.entry_symbol();
             self.emit("");
-            self.emit(".global _start");
+            self.emit(&format!(".global {}", entry));
             self.emit("");
-            self.emit("_start:");
+            self.emit(&format!("{}:", entry));
             self.emit("    pushq %rbp");
             self.emit("    movq %rsp, %rbp");
-            self.emit("    subq $256, %rsp");
+            let frame_reserve = self.emit_frame_reserve();
+            // The kernel (or dyld's call) leaves %rsp aligned, but pushing
+            // %rbp undid that
+            self.emit("    andq $-16, %rsp");
+            self.stack_depth = 0;
+            if self.target == Target::Linux {
+                self.emit_init_calls();
+            }
 
-            // Generate non-function code
-            for node in ast {
-                if let AstNode::FunctionDecl(_, _, _) = node {
-                    continue;
+            // Generate non-function code, or call the tests instead
+            if let Some(runner) = &test_runner {
+                self.generate_test_runner(runner);
+            } else {
+                for node in ast {
+                    if let AstNode::FunctionDecl(_, _, _, _) = node {
+                        continue;
+                    }
+                    self.generate_node(node);
                 }
-                self.generate_node(node);
             }
+            self.patch_frame_reserve(frame_reserve);
 
             // Exit
             self.emit("");
             self.emit("    movq %rbp, %rsp");
             self.emit("    popq %rbp");
-            self.emit("    movq $60, %rax");
+            self.emit_syscall_number(Syscall::Exit);
             self.emit("    xorq %rdi, %rdi");
             self.emit("    syscall");
+
+            // Mark the files whose top-level code was inlined above
+            let markers = std::mem::take(&mut self.included_markers);
+            if !markers.is_empty() {
+                self.emit("");
+                self.emit(self.target.read_only_section());
+                for marker in markers {
+                    self.emit(&format!(".global {}", marker));
+                    self.emit(&format!("{}:", marker));
+                    self.emit("    .byte 1");
+                }
+            }
         } else {
             // For included files, only generate non-function code if it exists
             let has_non_function_code = ast
                 .iter()
-                .any(|node| !matches!(node, AstNode::FunctionDecl(_, _, _)));
+                .any(|node| !matches!(node, AstNode::FunctionDecl(_, _, _, _)));
 
             if has_non_function_code {
                 // Create an initialization function for this file
                 let init_label = format!("__init_{}", self.label_count);
                 self.label_count += 1;
 
+                let end_label = self.get_new_label();
+
                 self.emit("");
                 self.emit(&format!("{}:", init_label));
                 self.emit("    pushq %rbp");
                 self.emit("    movq %rsp, %rbp");
-                self.emit("    subq $256, %rsp");
+                let frame_reserve = self.emit_frame_reserve();
+                self.stack_depth = 0;
+
+                // Skip the code if the main file already inlined it, which
+                // replaces the weak 0 marker below with its own 1
+                let marker = self.unit_marker.clone();
+                if !marker.is_empty() {
+                    self.emit(&format!("    cmpb $0, {}(%rip)", marker));
+                    self.emit(&format!("    jne {}", end_label));
+                }
 
                 // Generate non-function code
                 for node in ast {
-                    if let AstNode::FunctionDecl(_, _, _) = node {
+                    if let AstNode::FunctionDecl(_, _, _, _) = node {
                         continue;
                     }
                     self.generate_node(node);
                 }
+                self.patch_frame_reserve(frame_reserve);
 
+                self.emit(&format!("{}:", end_label));
                 self.emit("    movq %rbp, %rsp");
                 self.emit("    popq %rbp");
                 self.emit("    ret");
+
+                // Have it called before the main file's own code
+                self.emit("");
+                self.emit(self.target.init_section());
+                self.emit("    .p2align 3");
+                self.emit(&format!("    .quad {}", init_label));
+
+                if !marker.is_empty() {
+                    self.emit("");
+                    self.emit(self.target.read_only_section());
+                    match self.target {
+                        Target::Linux => self.emit(&format!(".weak {}", marker)),
+                        Target::MacOs => {
+                            self.emit(&format!(".global {}", marker));
+                            self.emit(&format!(".weak_definition {}", marker));
+                        }
+                    }
+                    self.emit(&format!("{}:", marker));
+                    self.emit("    .byte 0");
+                }
             }
         }
 
-        self.assembly.clone()
+        // Mark the stack as non-executable so the linker doesn't warn about
+        // (or grant) an executable stack for this object. Mach-O stacks are
+        // never executable.
+        if self.target == Target::Linux {
+            self.emit("");
+            self.emit(".section .note.GNU-stack,\"\",@progbits");
+        }
+
+        // Hand the buffer over rather than copying it; the next call
+        // starts from an empty one either way
+        std::mem::take(&mut self.assembly)
     }
 }
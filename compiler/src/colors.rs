@@ -112,6 +112,14 @@ pub fn error_style() -> Style {
     Style::new().bold().fg(Color::Red)
 }
 
+pub fn warning_style() -> Style {
+    Style::new().bold().fg(Color::Yellow)
+}
+
+pub fn help_style() -> Style {
+    Style::new().bold().fg(Color::Green)
+}
+
 pub fn error_location_style() -> Style {
     Style::new().bold().fg(Color::Cyan)
 }
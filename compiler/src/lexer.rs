@@ -1,10 +1,13 @@
+use std::collections::HashMap;
 use std::fmt;
 
 // Error handling structures
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SourceLocation {
     pub line: usize,
     pub column: usize,
+    // Number of columns the pointer underlines, at least 1
+    pub width: usize,
     pub file: String,
 }
 
@@ -23,6 +26,146 @@ pub enum ErrorKind {
     SyntaxError(String),
     InvalidOperator(String),
     IOError(String),
+    DeniedWarning { kind: WarningKind, message: String },
+}
+
+// Categories of warnings, each of which can be allowed or denied by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    ShadowedParameter,
+}
+
+impl WarningKind {
+    pub const ALL: &'static [WarningKind] = &[WarningKind::ShadowedParameter];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::ShadowedParameter => "shadowed-parameter",
+        }
+    }
+
+    // Whether the warning is reported without being enabled with -W
+    pub fn enabled_by_default(&self) -> bool {
+        match self {
+            // Off until blocks get their own scope, since assigning to a
+            // parameter from a loop is often intended
+            WarningKind::ShadowedParameter => false,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WarningKind> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+}
+
+#[derive(Debug)]
+pub struct CompilerWarning {
+    pub kind: WarningKind,
+    pub message: String,
+    pub location: SourceLocation,
+    pub source_line: String,
+}
+
+impl CompilerWarning {
+    pub fn format_warning(&self) -> String {
+        use crate::colors::warning_style;
+
+        let mut warning = warning_style().apply(&format!(
+            "warning: {} [{}]\n",
+            self.message,
+            self.kind.name()
+        ));
+        warning.push_str(&format_snippet(&self.location, &self.source_line));
+        warning
+    }
+
+    // One-line JSON form, for --error-format=json
+    pub fn to_json(&self) -> String {
+        format_json_diagnostic("warning", Some(self.kind.name()), &self.message, &self.location, None)
+    }
+
+    // Turns the warning into an error, for categories passed to -D
+    pub fn into_error(self) -> CompilerError {
+        CompilerError::new(
+            ErrorKind::DeniedWarning {
+                kind: self.kind,
+                message: self.message,
+            },
+            self.location,
+            self.source_line,
+        )
+    }
+}
+
+// Renders a diagnostic as a single-line JSON object for tools and editors
+fn format_json_diagnostic(
+    level: &str,
+    code: Option<&str>,
+    message: &str,
+    location: &SourceLocation,
+    help: Option<&str>,
+) -> String {
+    let optional = |value: Option<&str>| value.map_or("null".to_string(), json_string);
+    format!(
+        "{{\"level\":\"{}\",\"code\":{},\"message\":{},\"file\":{},\"line\":{},\"column\":{},\"help\":{}}}",
+        level,
+        optional(code),
+        json_string(message),
+        json_string(&location.file),
+        location.line,
+        location.column,
+        optional(help)
+    )
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Renders the location, source line and pointer shared by all diagnostics
+fn format_snippet(location: &SourceLocation, source_line: &str) -> String {
+    use crate::colors::{error_location_style, error_pointer_style, error_source_style};
+
+    let mut snippet = String::new();
+
+    // Location
+    snippet.push_str(&error_location_style().apply(&format!(
+        "  --> {}:{}:{}\n",
+        location.file, location.line, location.column
+    )));
+
+    // Source line with line number
+    snippet.push_str(&error_source_style().apply(&format!(
+        "{:4} | {}\n",
+        location.line, source_line
+    )));
+
+    // Error pointer
+    let mut pointer = String::from("     | ");
+    for _ in 0..location.column - 1 {
+        pointer.push(' ');
+    }
+    pointer.push('^');
+    for _ in 1..location.width {
+        pointer.push('~');
+    }
+    snippet.push_str(&error_pointer_style().apply(&pointer));
+    snippet.push('\n');
+
+    snippet
 }
 
 impl CompilerError {
@@ -35,39 +178,45 @@ impl CompilerError {
     }
 
     pub fn format_error(&self) -> String {
-        use crate::colors::{
-            error_location_style, error_pointer_style, error_source_style, error_style,
-        };
+        use crate::colors::{error_style, help_style};
 
         let mut error = String::new();
 
         // Error message
         error.push_str(&error_style().apply(&format!("error: {}\n", self.get_error_message())));
+        error.push_str(&format_snippet(&self.location, &self.source_line));
 
-        // Location
-        error.push_str(&error_location_style().apply(&format!(
-            "  --> {}:{}:{}\n",
-            self.location.file, self.location.line, self.location.column
-        )));
-
-        // Source line with line number
-        error.push_str(&error_source_style().apply(&format!(
-            "{:4} | {}\n",
-            self.location.line, self.source_line
-        )));
-
-        // Error pointer
-        let mut pointer = String::from("     | ");
-        for _ in 0..self.location.column - 1 {
-            pointer.push(' ');
+        // Suggested fix, if there's an obvious one
+        if let Some(help) = self.help() {
+            error.push_str(&help_style().apply(&format!("     = help: {}", help)));
+            error.push('\n');
         }
-        pointer.push('^');
-        error.push_str(&error_pointer_style().apply(&pointer));
-        error.push('\n');
 
         error
     }
 
+    // One-line JSON form, for --error-format=json
+    pub fn to_json(&self) -> String {
+        let code = match &self.kind {
+            ErrorKind::DeniedWarning { kind, .. } => Some(kind.name()),
+            _ => None,
+        };
+        format_json_diagnostic("error", code, &self.get_error_message(), &self.location, self.help())
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        match &self.kind {
+            // A statement ended by a closing brace or the end of the file is
+            // almost always just missing its semicolon
+            ErrorKind::UnexpectedToken { expected, found }
+                if expected == ";" && (found == "}" || found == "end of file") =>
+            {
+                Some("add a semicolon")
+            }
+            _ => None,
+        }
+    }
+
     fn get_error_message(&self) -> String {
         match &self.kind {
             ErrorKind::UnexpectedToken { expected, found } => {
@@ -83,6 +232,9 @@ impl CompilerError {
             ErrorKind::IOError(msg) => {
                 format!("IO error: {}", msg)
             }
+            ErrorKind::DeniedWarning { kind, message } => {
+                format!("{} [-D {}]", message, kind.name())
+            }
         }
     }
 }
@@ -90,15 +242,25 @@ impl CompilerError {
 pub type Result<T> = std::result::Result<T, CompilerError>;
 
 #[derive(Debug, Clone, PartialEq)]
-#[allow(dead_code)]
+#[allow(dead_code, clippy::upper_case_acronyms)]
 pub enum TokenType {
     Number(i64),
     Align,
+    CConv,
+    Test,
     Identifier(String),
     Plus,
     Minus,
     Multiply,
     Divide,
+    Modulo,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    Not,
     Assign,
     Semicolon,
     LParen,
@@ -110,36 +272,78 @@ pub enum TokenType {
     And,
     Or,
     While,
+    Do,
+    For,
+    Array,
     Equals,
     NotEquals,
     Less,
     Greater,
     Function,
     Return,
+    Break,
+    Continue,
     Comma,
-    StringLiteral(String),
+    // The bytes a string literal holds, which needn't be valid UTF-8
+    StringLiteral(Vec<u8>),
     LessEqual,
     GreaterEqual,
     Include,
     From,
     Asm,
+    True,
+    False,
     Colon,
+    Question,
     LeftBracket,
     RightBracket,
     Dot,
     EOF,
 }
 
+impl TokenType {
+    // The source text of a reserved keyword, if this token is one
+    pub fn keyword(&self) -> Option<&'static str> {
+        match self {
+            TokenType::If => Some("if"),
+            TokenType::Else => Some("else"),
+            TokenType::While => Some("while"),
+            TokenType::Do => Some("do"),
+            TokenType::For => Some("for"),
+            TokenType::Array => Some("array"),
+            TokenType::Function => Some("fn"),
+            TokenType::Return => Some("return"),
+            TokenType::Break => Some("break"),
+            TokenType::Continue => Some("continue"),
+            TokenType::From => Some("from"),
+            TokenType::Asm => Some("asm"),
+            TokenType::True => Some("true"),
+            TokenType::False => Some("false"),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for TokenType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             TokenType::Number(n) => write!(f, "number {}", n),
             TokenType::Align => write!(f, "@align"),
+            TokenType::CConv => write!(f, "@cconv"),
+            TokenType::Test => write!(f, "@test"),
             TokenType::Identifier(s) => write!(f, "identifier {}", s),
             TokenType::Plus => write!(f, "+"),
             TokenType::Minus => write!(f, "-"),
             TokenType::Multiply => write!(f, "*"),
             TokenType::Divide => write!(f, "/"),
+            TokenType::Modulo => write!(f, "%"),
+            TokenType::BitAnd => write!(f, "&"),
+            TokenType::BitOr => write!(f, "|"),
+            TokenType::BitXor => write!(f, "^"),
+            TokenType::BitNot => write!(f, "~"),
+            TokenType::ShiftLeft => write!(f, "<<"),
+            TokenType::ShiftRight => write!(f, ">>"),
+            TokenType::Not => write!(f, "!"),
             TokenType::Assign => write!(f, "="),
             TokenType::Semicolon => write!(f, ";"),
             TokenType::LParen => write!(f, "("),
@@ -151,6 +355,9 @@ impl fmt::Display for TokenType {
             TokenType::And => write!(f, "&&"),
             TokenType::Or => write!(f, "||"),
             TokenType::While => write!(f, "while"),
+            TokenType::Do => write!(f, "do"),
+            TokenType::For => write!(f, "for"),
+            TokenType::Array => write!(f, "array"),
             TokenType::Equals => write!(f, "=="),
             TokenType::NotEquals => write!(f, "!="),
             TokenType::Less => write!(f, "<"),
@@ -159,12 +366,17 @@ impl fmt::Display for TokenType {
             TokenType::GreaterEqual => write!(f, ">="),
             TokenType::Function => write!(f, "fn"),
             TokenType::Return => write!(f, "return"),
+            TokenType::Break => write!(f, "break"),
+            TokenType::Continue => write!(f, "continue"),
             TokenType::Comma => write!(f, ","),
-            TokenType::StringLiteral(s) => write!(f, "string \"{}\"", s),
+            TokenType::StringLiteral(s) => write!(f, "string \"{}\"", String::from_utf8_lossy(s)),
             TokenType::Include => write!(f, "@include"),
             TokenType::From => write!(f, "from"),
             TokenType::Asm => write!(f, "asm"),
+            TokenType::True => write!(f, "true"),
+            TokenType::False => write!(f, "false"),
             TokenType::Colon => write!(f, ":"),
+            TokenType::Question => write!(f, "?"),
             TokenType::LeftBracket => write!(f, "["),
             TokenType::RightBracket => write!(f, "]"),
             TokenType::Dot => write!(f, "."),
@@ -192,21 +404,91 @@ pub struct Lexer {
 
 impl Lexer {
     pub fn new(input: &str, filename: String) -> Self {
+        // Normalize CRLF line endings so columns match the stored source lines
+        let input = input.replace("\r\n", "\n");
         let source_lines: Vec<String> = input.lines().map(String::from).collect();
-        Lexer {
+        let mut lexer = Lexer {
             input: input.chars().collect(),
             position: 0,
             line: 1,
             column: 1,
             filename,
             source_lines,
+        };
+
+        // Skip a leading shebang line so Zed files can be run as scripts,
+        // leaving the newline so line numbers stay accurate
+        if input.starts_with("#!") {
+            while lexer.peek().is_some_and(|ch| ch != '\n') {
+                lexer.advance();
+            }
         }
+
+        lexer
+    }
+
+    // The functions defined outside any block and their parameter counts,
+    // found with a quick pass over the tokens so that calls can come before
+    // the definition
+    pub fn scan_function_definitions(&self) -> HashMap<String, usize> {
+        let source: String = self.input.iter().collect();
+        let mut scanner = Lexer::new(&source, self.filename.clone());
+        let mut functions = HashMap::new();
+        let mut depth = 0usize;
+        // The function after a top-level `fn`, until its body or `;` shows
+        // whether it's a definition or a predeclaration
+        let mut pending: Option<(String, usize)> = None;
+        let mut in_parameters = false;
+
+        loop {
+            let token = match scanner.next_token() {
+                Ok(token) => token,
+                Err(_) => {
+                    scanner.skip_char();
+                    continue;
+                }
+            };
+            match token.token_type {
+                TokenType::EOF => break,
+                TokenType::Function if depth == 0 => {
+                    if let Ok(Token {
+                        token_type: TokenType::Identifier(name),
+                        ..
+                    }) = scanner.next_token()
+                    {
+                        pending = Some((name, 0));
+                    }
+                }
+                TokenType::LParen if pending.is_some() => in_parameters = true,
+                TokenType::RParen => in_parameters = false,
+                TokenType::Identifier(_) if in_parameters => {
+                    if let Some((_, count)) = &mut pending {
+                        *count += 1;
+                    }
+                }
+                TokenType::LBrace => {
+                    if depth == 0 {
+                        functions.extend(pending.take());
+                    }
+                    depth += 1;
+                }
+                TokenType::RBrace => depth = depth.saturating_sub(1),
+                TokenType::Semicolon if depth == 0 => pending = None,
+                _ => {}
+            }
+        }
+
+        functions
     }
 
     fn peek(&self) -> Option<char> {
         self.input.get(self.position).copied()
     }
 
+    fn peek_next(&self) -> Option<char> {
+        self.input.get(self.position + 1).copied()
+    }
+
     fn advance(&mut self) {
         if let Some(ch) = self.peek() {
             if ch == '\n' {
@@ -219,21 +501,22 @@ impl Lexer {
         }
     }
 
-    fn process_escape_sequence(&mut self) -> Result<char> {
+    fn process_escape_sequence(&mut self) -> Result<u8> {
         match self.peek() {
             Some(ch) => {
                 self.advance();
                 match ch {
-                    'n' => Ok('\n'),
-                    't' => Ok('\t'),
-                    'r' => Ok('\r'),
-                    '\\' => Ok('\\'),
-                    '"' => Ok('"'),
-                    '0' => Ok('\0'),
-                    'b' => Ok('\x08'), // backspace
-                    'f' => Ok('\x0C'), // form feed
-                    'v' => Ok('\x0B'), // vertical tab
-                    '\'' => Ok('\''),
+                    'n' => Ok(b'\n'),
+                    't' => Ok(b'\t'),
+                    'r' => Ok(b'\r'),
+                    '\\' => Ok(b'\\'),
+                    '"' => Ok(b'"'),
+                    '0' => Ok(b'\0'),
+                    'b' => Ok(0x08), // backspace
+                    'f' => Ok(0x0C), // form feed
+                    'v' => Ok(0x0B), // vertical tab
+                    '\'' => Ok(b'\''),
+                    'x' => self.process_hex_escape(),
                     _ => Err(self.create_error(ErrorKind::SyntaxError(format!(
                         "invalid escape sequence: \\{}",
                         ch
@@ -246,6 +529,11 @@ impl Lexer {
         }
     }
 
+    // Skips the current character; used to resume lexing after an error
+    pub fn skip_char(&mut self) {
+        self.advance();
+    }
+
     fn skip_whitespace(&mut self) {
         while let Some(ch) = self.peek() {
             if !ch.is_whitespace() {
@@ -255,6 +543,72 @@ impl Lexer {
         }
     }
 
+    // Reads the two hex digits of a `\xNN` escape, giving a byte value
+    fn process_hex_escape(&mut self) -> Result<u8> {
+        let mut value = 0u8;
+        for _ in 0..2 {
+            match self.peek().and_then(|ch| ch.to_digit(16)) {
+                Some(digit) => {
+                    value = value * 16 + digit as u8;
+                    self.advance();
+                }
+                None => {
+                    return Err(self.create_error(ErrorKind::SyntaxError(
+                        "incomplete hex escape: expected two hex digits after \\x".to_string(),
+                    )))
+                }
+            }
+        }
+
+        Ok(value)
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    // Source text between two (line, column) positions, end exclusive, with
+    // lines joined by spaces
+    pub fn source_text(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let mut text = Vec::new();
+        for line in start.0..=end.0 {
+            let Some(source) = self.source_lines.get(line - 1) else {
+                break;
+            };
+            let chars: Vec<char> = source.chars().collect();
+            let from = if line == start.0 { start.1 - 1 } else { 0 };
+            let to = if line == end.0 { end.1 - 1 } else { chars.len() };
+            let from = from.min(chars.len());
+            let to = to.clamp(from, chars.len());
+            text.push(chars[from..to].iter().collect::<String>().trim().to_string());
+        }
+        text.join(" ")
+    }
+
+    pub fn create_warning(
+        &self,
+        kind: WarningKind,
+        message: String,
+        line: usize,
+        column: usize,
+    ) -> CompilerWarning {
+        CompilerWarning {
+            kind,
+            message,
+            location: self.location(line, column),
+            source_line: self.source_lines.get(line - 1).cloned().unwrap_or_default(),
+        }
+    }
+
+    pub fn location(&self, line: usize, column: usize) -> SourceLocation {
+        SourceLocation {
+            line,
+            column,
+            width: 1,
+            file: self.filename.clone(),
+        }
+    }
+
     pub fn create_error(&self, kind: ErrorKind) -> CompilerError {
         let source_line = if self.line <= self.source_lines.len() {
             self.source_lines[self.line - 1].clone()
@@ -267,16 +621,39 @@ impl Lexer {
             SourceLocation {
                 line: self.line,
                 column: self.column,
+                width: 1,
                 file: self.filename.clone(),
             },
             source_line,
         )
     }
 
+    // Reports an error on the token just read, underlining all of it. The
+    // lexer sits right after that token, so its width is the distance back
+    // to the token's start.
+    pub fn create_token_error(&self, kind: ErrorKind, token: &Token) -> CompilerError {
+        self.create_span_error(kind, token.line, token.column)
+    }
+
+    // Reports an error underlining everything from `line:column` up to the
+    // lexer's position, or a single column if that spans lines
+    pub fn create_span_error(&self, kind: ErrorKind, line: usize, column: usize) -> CompilerError {
+        let width = if self.line == line && self.column > column {
+            self.column - column
+        } else {
+            1
+        };
+        let mut location = self.location(line, column);
+        location.width = width;
+        let source_line = self.source_lines.get(line - 1).cloned().unwrap_or_default();
+
+        CompilerError::new(kind, location, source_line)
+    }
+
     fn read_string(&mut self) -> Result<Token> {
         let start_column = self.column;
         self.advance(); // Skip opening quote
-        let mut string = String::new();
+        let mut string = Vec::new();
 
         while let Some(ch) = self.peek() {
             match ch {
@@ -290,11 +667,21 @@ impl Lexer {
                 }
                 '\\' => {
                     self.advance();
-                    let escaped_char = self.process_escape_sequence()?;
-                    string.push(escaped_char);
+
+                    // A backslash at the end of a line splices the next line
+                    // onto this one, dropping both the backslash and newline
+                    if self.peek() == Some('\n') {
+                        self.advance();
+                        continue;
+                    }
+
+                    // An escape stands for a single byte, even `\x80` and up
+                    let escaped_byte = self.process_escape_sequence()?;
+                    string.push(escaped_byte);
                 }
                 _ => {
-                    string.push(ch);
+                    let mut buffer = [0; 4];
+                    string.extend_from_slice(ch.encode_utf8(&mut buffer).as_bytes());
                     self.advance();
                 }
             }
@@ -305,29 +692,163 @@ impl Lexer {
         )))
     }
 
+    // A character literal is just another way to write a byte value: an
+    // escape, or a character whose code point is at most 255
+    fn read_char(&mut self) -> Result<Token> {
+        let start_line = self.line;
+        let start_column = self.column;
+        self.advance(); // Skip opening quote
+
+        let value = match self.peek() {
+            Some('\'') => {
+                self.advance();
+                return Err(self.create_span_error(
+                    ErrorKind::SyntaxError("empty character literal".to_string()),
+                    start_line,
+                    start_column,
+                ));
+            }
+            Some('\\') => {
+                self.advance();
+                self.process_escape_sequence()? as u32
+            }
+            Some(ch) if ch != '\n' => {
+                self.advance();
+                ch as u32
+            }
+            _ => {
+                return Err(self.create_error(ErrorKind::SyntaxError(
+                    "unterminated character literal".to_string(),
+                )))
+            }
+        };
+
+        if self.peek() == Some('\'') {
+            self.advance();
+            if value > 0xFF {
+                return Err(self.create_span_error(
+                    ErrorKind::SyntaxError(format!(
+                        "character literal doesn't fit in a byte (U+{:04X}); use a string literal for its UTF-8 bytes",
+                        value
+                    )),
+                    start_line,
+                    start_column,
+                ));
+            }
+            return Ok(Token {
+                token_type: TokenType::Number(value as i64),
+                line: start_line,
+                column: start_column,
+            });
+        }
+
+        // Find the closing quote so the whole literal can be underlined
+        while let Some(ch) = self.peek() {
+            if ch == '\n' {
+                break;
+            }
+            self.advance();
+            if ch == '\'' {
+                return Err(self.create_span_error(
+                    ErrorKind::SyntaxError(
+                        "character literal holds more than one character".to_string(),
+                    ),
+                    start_line,
+                    start_column,
+                ));
+            }
+        }
+
+        Err(self.create_error(ErrorKind::SyntaxError(
+            "unterminated character literal".to_string(),
+        )))
+    }
+
     fn read_number(&mut self) -> Result<Token> {
         let start_column = self.column;
+
+        // 0x, 0o and 0b prefixes select another base
+        if self.peek() == Some('0') {
+            let radix = match self.peek_next() {
+                Some('x' | 'X') => Some((16, "hexadecimal")),
+                Some('o' | 'O') => Some((8, "octal")),
+                Some('b' | 'B') => Some((2, "binary")),
+                _ => None,
+            };
+            if let Some((radix, base_name)) = radix {
+                return self.read_prefixed_number(start_column, radix, base_name);
+            }
+        }
+
         let mut number = String::new();
 
         while let Some(ch) = self.peek() {
-            if !ch.is_digit(10) {
+            if !ch.is_ascii_digit() && ch != '_' {
                 break;
             }
             number.push(ch);
             self.advance();
         }
 
-        match number.parse() {
-            Ok(n) => Ok(Token {
-                token_type: TokenType::Number(n),
-                line: self.line,
-                column: start_column,
-            }),
-            Err(_) => Err(self.create_error(ErrorKind::SyntaxError(format!(
-                "invalid number: {}",
-                number
-            )))),
+        let message = match strip_digit_separators(&number) {
+            None => format!("misplaced underscore in number {}", number),
+            Some(digits) => match digits.parse() {
+                Ok(n) => {
+                    return Ok(Token {
+                        token_type: TokenType::Number(n),
+                        line: self.line,
+                        column: start_column,
+                    })
+                }
+                Err(_) => format!("invalid number: {}", number),
+            },
+        };
+
+        Err(self.create_span_error(ErrorKind::SyntaxError(message), self.line, start_column))
+    }
+
+    fn read_prefixed_number(
+        &mut self,
+        start_column: usize,
+        radix: u32,
+        base_name: &str,
+    ) -> Result<Token> {
+        let prefix: String = [self.peek(), self.peek_next()].into_iter().flatten().collect();
+        self.advance();
+        self.advance();
+
+        // Take every letter and digit so that `0xGG` is reported as a whole
+        let mut digits = String::new();
+        while let Some(ch) = self.peek() {
+            if !ch.is_ascii_alphanumeric() && ch != '_' {
+                break;
+            }
+            digits.push(ch);
+            self.advance();
         }
+
+        let message = if digits.is_empty() {
+            format!("{} literal has no digits after `{}`", base_name, prefix)
+        } else if let Some(bad) = digits.chars().find(|&ch| ch != '_' && !ch.is_digit(radix)) {
+            format!("invalid digit '{}' in {} literal {}{}", bad, base_name, prefix, digits)
+        } else if let Some(stripped) = strip_digit_separators(&digits) {
+            // These spell out bit patterns, so the top bit may be set and
+            // the value wraps to a negative number
+            match u64::from_str_radix(&stripped, radix) {
+                Ok(n) => {
+                    return Ok(Token {
+                        token_type: TokenType::Number(n as i64),
+                        line: self.line,
+                        column: start_column,
+                    })
+                }
+                Err(_) => format!("{} literal {}{} doesn't fit in 64 bits", base_name, prefix, digits),
+            }
+        } else {
+            format!("misplaced underscore in {} literal {}{}", base_name, prefix, digits)
+        };
+
+        Err(self.create_span_error(ErrorKind::SyntaxError(message), self.line, start_column))
     }
 
     pub fn skip_whitespace_and_comments(&mut self) {
@@ -403,7 +924,7 @@ impl Lexer {
                 let start_column = self.column;
                 self.advance();
 
-                // First check for <= operator
+                // First check for <= and << operators
                 if self.peek() == Some('=') {
                     self.advance();
                     return Ok(Token {
@@ -412,31 +933,38 @@ impl Lexer {
                         column: start_column,
                     });
                 }
+                if self.peek() == Some('<') {
+                    self.advance();
+                    return Ok(Token {
+                        token_type: TokenType::ShiftLeft,
+                        line: self.line,
+                        column: start_column,
+                    });
+                }
 
                 // Then check for std/ include
-                if self.position + 3 < self.input.len() {
-                    if self.input[self.position] == 's'
-                        && self.input[self.position + 1] == 't'
-                        && self.input[self.position + 2] == 'd'
-                        && self.input[self.position + 3] == '/'
-                    {
-                        self.position += 4;
-                        self.column += 4;
+                if self.position + 3 < self.input.len()
+                    && self.input[self.position] == 's'
+                    && self.input[self.position + 1] == 't'
+                    && self.input[self.position + 2] == 'd'
+                    && self.input[self.position + 3] == '/'
+                {
+                    self.position += 4;
+                    self.column += 4;
 
-                        // Collect everything until >
-                        let mut path = String::new();
-                        while let Some(ch) = self.peek() {
-                            if ch == '>' {
-                                self.advance();
-                                return Ok(Token {
-                                    token_type: TokenType::StringLiteral(format!("std/{}", path)),
-                                    line: self.line,
-                                    column: start_column,
-                                });
-                            }
-                            path.push(ch);
+                    // Collect everything until >
+                    let mut path = String::new();
+                    while let Some(ch) = self.peek() {
+                        if ch == '>' {
                             self.advance();
+                            return Ok(Token {
+                                token_type: TokenType::StringLiteral(format!("std/{}", path).into_bytes()),
+                                line: self.line,
+                                column: start_column,
+                            });
                         }
+                        path.push(ch);
+                        self.advance();
                     }
                 }
 
@@ -464,6 +992,16 @@ impl Lexer {
                         line: self.line,
                         column: self.column - identifier.len() - 1,
                     }),
+                    "cconv" => Ok(Token {
+                        token_type: TokenType::CConv,
+                        line: self.line,
+                        column: self.column - identifier.len() - 1,
+                    }),
+                    "test" => Ok(Token {
+                        token_type: TokenType::Test,
+                        line: self.line,
+                        column: self.column - identifier.len() - 1,
+                    }),
                     _ => Err(self.create_error(ErrorKind::SyntaxError(format!(
                         "unknown directive @{}",
                         identifier
@@ -506,6 +1044,14 @@ impl Lexer {
                         column: self.column - 1,
                     })
                 }
+                '%' => {
+                    self.advance();
+                    Ok(Token {
+                        token_type: TokenType::Modulo,
+                        line: self.line,
+                        column: self.column - 1,
+                    })
+                }
                 '/' => {
                     self.advance();
                     Ok(Token {
@@ -541,9 +1087,11 @@ impl Lexer {
                             column: self.column - 2,
                         })
                     } else {
-                        Err(self.create_error(ErrorKind::SyntaxError(
-                            "unexpected '!' - did you mean '!='?".to_string(),
-                        )))
+                        Ok(Token {
+                            token_type: TokenType::Not,
+                            line: self.line,
+                            column: self.column - 1,
+                        })
                     }
                 }
                 '>' => {
@@ -555,6 +1103,13 @@ impl Lexer {
                             line: self.line,
                             column: self.column - 2,
                         })
+                    } else if self.peek() == Some('>') {
+                        self.advance();
+                        Ok(Token {
+                            token_type: TokenType::ShiftRight,
+                            line: self.line,
+                            column: self.column - 2,
+                        })
                     } else {
                         Ok(Token {
                             token_type: TokenType::Greater,
@@ -612,6 +1167,7 @@ impl Lexer {
                     })
                 }
                 '"' => self.read_string(),
+                '\'' => self.read_char(),
                 ':' => {
                     let col = self.column;
                     self.advance();
@@ -621,6 +1177,15 @@ impl Lexer {
                         column: col,
                     })
                 }
+                '?' => {
+                    let col = self.column;
+                    self.advance();
+                    Ok(Token {
+                        token_type: TokenType::Question,
+                        line: self.line,
+                        column: col,
+                    })
+                }
                 '[' => {
                     self.advance();
                     Ok(Token {
@@ -648,14 +1213,50 @@ impl Lexer {
                             column: start_column,
                         })
                     } else {
-                        Err(self.create_error(ErrorKind::SyntaxError(
-                            "unexpected '&' - did you mean '&&'?".to_string(),
-                        )))
+                        Ok(Token {
+                            token_type: TokenType::BitAnd,
+                            line: self.line,
+                            column: start_column,
+                        })
+                    }
+                }
+                '|' => {
+                    let start_column = self.column;
+                    self.advance();
+                    if self.peek() == Some('|') {
+                        self.advance();
+                        Ok(Token {
+                            token_type: TokenType::Or,
+                            line: self.line,
+                            column: start_column,
+                        })
+                    } else {
+                        Ok(Token {
+                            token_type: TokenType::BitOr,
+                            line: self.line,
+                            column: start_column,
+                        })
                     }
                 }
+                '^' => {
+                    self.advance();
+                    Ok(Token {
+                        token_type: TokenType::BitXor,
+                        line: self.line,
+                        column: self.column - 1,
+                    })
+                }
+                '~' => {
+                    self.advance();
+                    Ok(Token {
+                        token_type: TokenType::BitNot,
+                        line: self.line,
+                        column: self.column - 1,
+                    })
+                }
                 _ => Err(self.create_error(ErrorKind::SyntaxError(format!(
-                    "unexpected character: {}",
-                    ch
+                    "unexpected character '{}'",
+                    ch.escape_debug()
                 )))),
             },
         }
@@ -694,10 +1295,17 @@ impl Lexer {
                 "if" => TokenType::If,
                 "else" => TokenType::Else,
                 "while" => TokenType::While,
+                "do" => TokenType::Do,
+                "for" => TokenType::For,
+                "array" => TokenType::Array,
                 "fn" => TokenType::Function,
                 "return" => TokenType::Return,
+                "break" => TokenType::Break,
+                "continue" => TokenType::Continue,
                 "from" => TokenType::From,
                 "asm" => TokenType::Asm,
+                "true" => TokenType::True,
+                "false" => TokenType::False,
                 _ => TokenType::Identifier(identifier),
             }
         };
@@ -709,3 +1317,11 @@ impl Lexer {
         })
     }
 }
+
+// Drops `_` digit separators, which may only sit between two digits
+fn strip_digit_separators(digits: &str) -> Option<String> {
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return None;
+    }
+    Some(digits.replace('_', ""))
+}
@@ -0,0 +1,22 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbose(enabled: bool) {
+    VERBOSE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+// Prints a trace line to stderr when --verbose is given
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::log::is_verbose() {
+            eprintln!("verbose: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+pub(crate) use verbose;
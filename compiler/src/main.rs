@@ -2,53 +2,371 @@ mod ast;
 mod codegen;
 mod colors;
 mod lexer;
+mod log;
 mod parser;
+mod symbols;
 
-use codegen::CodeGenerator;
-use lexer::{Lexer, Result};
+use ast::AstNode;
+use codegen::{CodeGenerator, Target};
+use colors::error_style;
+use lexer::{CompilerError, Lexer, Result, WarningKind};
+use log::verbose;
+use std::collections::HashMap;
 use parser::Parser;
+use symbols::SymbolManifest;
 use std::env;
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::process;
+use std::process::{self, Command};
+use std::time::Instant;
+
+// Name used in diagnostics for source read from stdin
+const STDIN_FILENAME: &str = "<stdin>";
 
 fn is_main_file(filepath: &str) -> bool {
+    // Source piped through stdin is a standalone program
+    if filepath == STDIN_FILENAME {
+        return true;
+    }
+
     Path::new(filepath)
         .file_name()
         .and_then(|name| name.to_str())
-        .map_or(false, |name| name == "main.zed")
+        .is_some_and(|name| name == "main.zed")
+}
+
+const DEFAULT_MAX_ERRORS: usize = 20;
+
+// Environment variable overriding the default stdlib location
+const STDLIB_PATH_ENV: &str = "ZED_STDLIB_PATH";
+
+// Resolves the stdlib path: --stdlib-path first, then $ZED_STDLIB_PATH, then
+// the default location in the home directory
+fn resolve_stdlib_path(flag: Option<PathBuf>) -> Option<PathBuf> {
+    flag.or_else(|| {
+        env::var_os(STDLIB_PATH_ENV)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    })
+    .or_else(|| Parser::get_default_stdlib_path().ok())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+// How diagnostics are printed: for people, or one JSON object per line
+#[derive(Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+fn parse_target(name: &str) -> Target {
+    Target::from_name(name).unwrap_or_else(|| {
+        eprintln!("error: unknown target `{}` (expected linux or macos)", name);
+        process::exit(1);
+    })
+}
+
+fn parse_error_format(name: &str) -> ErrorFormat {
+    match name {
+        "human" => ErrorFormat::Human,
+        "json" => ErrorFormat::Json,
+        _ => {
+            eprintln!("error: unknown error format `{}` (expected human or json)", name);
+            process::exit(1);
+        }
+    }
+}
+
+struct CompileOptions {
+    stdlib_path: Option<PathBuf>,
+    include_dirs: Vec<PathBuf>,
+    lint_levels: HashMap<WarningKind, LintLevel>,
+    debug_assertions: bool,
+    force_main: bool,
+    emit_symbols: bool,
+    error_format: ErrorFormat,
+    test: bool,
+    target: Target,
 }
 
-fn compile(source: &str, filename: &str, stdlib_path: Option<PathBuf>) -> Result<String> {
+// Output of a successful parse
+struct Parsed {
+    ast: Vec<AstNode>,
+    included_files: Vec<PathBuf>,
+}
+
+// Output of a successful compilation
+struct Compiled {
+    assembly: String,
+    symbols: Option<String>,
+}
+
+fn parse(
+    source: &str,
+    filename: &str,
+    options: &CompileOptions,
+) -> std::result::Result<Parsed, Vec<CompilerError>> {
     let lexer = Lexer::new(source, filename.to_string());
-    let mut parser = Parser::new(lexer, Path::new(filename))?;
+    let mut parser = Parser::new(lexer, Path::new(filename)).map_err(|e| vec![e])?;
 
-    if let Some(path) = stdlib_path {
-        parser.set_stdlib_path(path);
+    if let Some(path) = &options.stdlib_path {
+        parser.set_stdlib_path(path.clone());
+    }
+    for dir in &options.include_dirs {
+        parser.add_include_dir(dir.clone());
     }
 
-    let ast = parser.parse_program()?;
-    let mut generator = CodeGenerator::new(is_main_file(filename));
-    Ok(generator.generate(&ast))
+    let result = parser.parse();
+
+    // Print warnings, collecting denied ones alongside the errors
+    let mut denied = Vec::new();
+    for warning in parser.take_warnings() {
+        let level = options
+            .lint_levels
+            .get(&warning.kind)
+            .copied()
+            .unwrap_or(if warning.kind.enabled_by_default() {
+                LintLevel::Warn
+            } else {
+                LintLevel::Allow
+            });
+
+        match level {
+            LintLevel::Allow => {}
+            LintLevel::Warn => match options.error_format {
+                ErrorFormat::Human => eprintln!("{}", warning.format_warning()),
+                ErrorFormat::Json => eprintln!("{}", warning.to_json()),
+            },
+            LintLevel::Deny => denied.push(warning.into_error()),
+        }
+    }
+
+    match result {
+        Ok(ast) if denied.is_empty() => Ok(Parsed {
+            ast,
+            included_files: parser.included_files(),
+        }),
+        Ok(_) => Err(denied),
+        Err(mut errors) => {
+            errors.extend(denied);
+            Err(errors)
+        }
+    }
+}
+
+// Parses the argument to -A/-D; `warnings` names every category
+fn parse_lint_name(name: &str) -> Vec<WarningKind> {
+    if name == "warnings" {
+        return WarningKind::ALL.to_vec();
+    }
+
+    match WarningKind::from_name(name) {
+        Some(kind) => vec![kind],
+        None => {
+            eprintln!("error: unknown warning `{}`", name);
+            process::exit(1);
+        }
+    }
+}
+
+fn compile(
+    source: &str,
+    filename: &str,
+    options: &CompileOptions,
+) -> std::result::Result<Compiled, Vec<CompilerError>> {
+    // Tokens are lexed on demand while parsing, so the two are timed together
+    let start = Instant::now();
+    let Parsed {
+        ast,
+        included_files,
+    } = parse(source, filename, options)?;
+    verbose!("lex+parse took {:.2?}", start.elapsed());
+
+    // A test runner is always a complete program
+    let is_main = options.force_main || options.test || is_main_file(filename);
+
+    let mut generator = CodeGenerator::new(is_main);
+    generator.set_source_name(filename);
+    let unit_path = fs::canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename));
+    generator.set_unit_path(&unit_path);
+    generator.set_included_files(&included_files);
+    generator.set_debug_assertions(options.debug_assertions);
+    generator.set_test_mode(options.test);
+    generator.set_target(options.target);
+
+    let start = Instant::now();
+    let assembly = generator.generate(&ast);
+    verbose!("codegen took {:.2?}", start.elapsed());
+
+    Ok(Compiled {
+        assembly,
+        symbols: options
+            .emit_symbols
+            .then(|| SymbolManifest::new(&ast, is_main, options.target).to_json()),
+    })
 }
 
 fn print_usage(program: &str) {
     eprintln!(
-        "Usage: {} <input.zed> -o <output.asm> [--stdlib-path <path>]",
+        "Usage: {} <input.zed> -o <output.asm> [--stdlib-path <path>] [-I <dir>]... [--max-errors <n>] [--debug-assertions] [--main] [--test] [--emit-symbols] [-A|-W|-D <warning>] [--error-format human|json] [--target linux|macos] [--verbose]",
         program
     );
+    eprintln!("       {} <input.zed> --check [--stdlib-path <path>]", program);
+    eprintln!("       {} run <input.zed> [args...]", program);
+    eprintln!("       {} --print-stdlib-path [--stdlib-path <path>]", program);
+    eprintln!("Use - as the input to read source from stdin.");
     process::exit(1);
 }
 
+// Prints at most `max_errors` diagnostics (0 means no limit), followed by a
+// summary of how many errors there were in total. JSON output is only the
+// diagnostics themselves.
+fn report_errors(errors: &[CompilerError], max_errors: usize, error_format: ErrorFormat) {
+    let shown = if max_errors == 0 {
+        errors.len()
+    } else {
+        max_errors.min(errors.len())
+    };
+
+    if error_format == ErrorFormat::Json {
+        for error in &errors[..shown] {
+            eprintln!("{}", error.to_json());
+        }
+        return;
+    }
+
+    for error in &errors[..shown] {
+        eprintln!("{}", error.format_error());
+    }
+
+    if shown < errors.len() {
+        eprintln!(
+            "note: {} more errors not shown (use --max-errors to show more)",
+            errors.len() - shown
+        );
+    }
+
+    let summary = if errors.len() == 1 {
+        "error: aborting due to 1 previous error".to_string()
+    } else {
+        format!("error: aborting due to {} previous errors", errors.len())
+    };
+    eprintln!("{}", error_style().apply(&summary));
+}
+
+// Runs an external tool, exiting with its output if it fails
+fn run_tool(command: &mut Command, name: &str) {
+    match command.output() {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => {
+            eprintln!("error: {} failed:", name);
+            eprint!("{}", String::from_utf8_lossy(&output.stderr));
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: couldn't run {}: {}", name, e);
+            process::exit(1);
+        }
+    }
+}
+
+// Compiles a program as a standalone executable in a temporary directory and
+// runs it with the given arguments, exiting with its status
+fn run(program: &str, args: &[String]) -> ! {
+    let Some(input_path) = args.first() else {
+        print_usage(program);
+        process::exit(1);
+    };
+
+    let source = fs::read_to_string(input_path).unwrap_or_else(|e| {
+        eprintln!("error: couldn't read {}: {}", input_path, e);
+        process::exit(1);
+    });
+
+    let options = CompileOptions {
+        stdlib_path: resolve_stdlib_path(None),
+        include_dirs: Vec::new(),
+        lint_levels: HashMap::new(),
+        debug_assertions: false,
+        force_main: true,
+        emit_symbols: false,
+        error_format: ErrorFormat::Human,
+        test: false,
+        target: Target::host(),
+    };
+
+    let compiled = compile(&source, input_path, &options).unwrap_or_else(|errors| {
+        report_errors(&errors, DEFAULT_MAX_ERRORS, ErrorFormat::Human);
+        process::exit(1);
+    });
+
+    let build_dir = env::temp_dir().join(format!("zedc-run-{}", process::id()));
+    if let Err(e) = fs::create_dir_all(&build_dir) {
+        eprintln!("error: couldn't create {}: {}", build_dir.display(), e);
+        process::exit(1);
+    }
+
+    let asm_path = build_dir.join("program.s");
+    let obj_path = build_dir.join("program.o");
+    let exe_path = build_dir.join("program");
+
+    if let Err(e) = fs::write(&asm_path, compiled.assembly) {
+        eprintln!("error: couldn't write to {}: {}", asm_path.display(), e);
+        process::exit(1);
+    }
+
+    run_tool(Command::new("as").arg(&asm_path).arg("-o").arg(&obj_path), "as");
+    // macOS programs have to link against libSystem, which the C compiler
+    // driver knows how to find
+    let linker = match options.target {
+        Target::Linux => "ld",
+        Target::MacOs => "cc",
+    };
+    run_tool(Command::new(linker).arg(&obj_path).arg("-o").arg(&exe_path), linker);
+
+    let status = Command::new(&exe_path).args(&args[1..]).status();
+    let _ = fs::remove_dir_all(&build_dir);
+
+    match status {
+        Ok(status) => process::exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            eprintln!("error: couldn't run {}: {}", input_path, e);
+            process::exit(1);
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 4 {
+    if args.len() < 2 {
         print_usage(&args[0]);
     }
 
+    if args[1] == "run" {
+        run(&args[0], &args[2..]);
+    }
+
     let mut input_path = None;
     let mut output_path = None;
     let mut stdlib_path = None;
+    let mut max_errors = DEFAULT_MAX_ERRORS;
+    let mut debug_assertions = false;
+    let mut check_only = false;
+    let mut force_main = false;
+    let mut emit_symbols = false;
+    let mut lint_levels = HashMap::new();
+    let mut print_stdlib_path = false;
+    let mut include_dirs = Vec::new();
+    let mut error_format = ErrorFormat::Human;
+    let mut test = false;
+    let mut target = Target::host();
     let mut i = 1;
 
     while i < args.len() {
@@ -71,6 +389,101 @@ fn main() -> Result<()> {
                     process::exit(1);
                 }
             }
+            "--max-errors" => {
+                if i + 1 < args.len() {
+                    max_errors = args[i + 1].parse().unwrap_or_else(|_| {
+                        eprintln!("error: --max-errors requires a number");
+                        process::exit(1);
+                    });
+                    i += 2;
+                } else {
+                    eprintln!("error: --max-errors requires a number");
+                    process::exit(1);
+                }
+            }
+            "-A" | "-W" | "-D" => {
+                if i + 1 < args.len() {
+                    let level = match args[i].as_str() {
+                        "-A" => LintLevel::Allow,
+                        "-W" => LintLevel::Warn,
+                        _ => LintLevel::Deny,
+                    };
+                    for kind in parse_lint_name(&args[i + 1]) {
+                        lint_levels.insert(kind, level);
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("error: {} requires a warning name", args[i]);
+                    process::exit(1);
+                }
+            }
+            "-I" => {
+                if i + 1 < args.len() {
+                    include_dirs.push(PathBuf::from(&args[i + 1]));
+                    i += 2;
+                } else {
+                    eprintln!("error: -I requires a directory");
+                    process::exit(1);
+                }
+            }
+            arg if arg.starts_with("-I") => {
+                include_dirs.push(PathBuf::from(&arg[2..]));
+                i += 1;
+            }
+            "--error-format" => {
+                if i + 1 < args.len() {
+                    error_format = parse_error_format(&args[i + 1]);
+                    i += 2;
+                } else {
+                    eprintln!("error: --error-format requires human or json");
+                    process::exit(1);
+                }
+            }
+            arg if arg.starts_with("--error-format=") => {
+                error_format = parse_error_format(&arg["--error-format=".len()..]);
+                i += 1;
+            }
+            "--target" => {
+                if i + 1 < args.len() {
+                    target = parse_target(&args[i + 1]);
+                    i += 2;
+                } else {
+                    eprintln!("error: --target requires linux or macos");
+                    process::exit(1);
+                }
+            }
+            arg if arg.starts_with("--target=") => {
+                target = parse_target(&arg["--target=".len()..]);
+                i += 1;
+            }
+            "--print-stdlib-path" => {
+                print_stdlib_path = true;
+                i += 1;
+            }
+            "--check" => {
+                check_only = true;
+                i += 1;
+            }
+            "--emit-symbols" => {
+                emit_symbols = true;
+                i += 1;
+            }
+            "--verbose" => {
+                log::set_verbose(true);
+                i += 1;
+            }
+            "--main" => {
+                force_main = true;
+                i += 1;
+            }
+            "--test" => {
+                test = true;
+                i += 1;
+            }
+            "--debug-assertions" => {
+                debug_assertions = true;
+                i += 1;
+            }
             _ => {
                 if input_path.is_none() {
                     input_path = Some(args[i].clone());
@@ -82,50 +495,101 @@ fn main() -> Result<()> {
         }
     }
 
+    let stdlib_path = resolve_stdlib_path(stdlib_path);
+
+    if print_stdlib_path {
+        match &stdlib_path {
+            Some(path) => println!("{}", path.display()),
+            None => {
+                eprintln!("error: couldn't determine the standard library path");
+                process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
     let input_path = input_path.unwrap_or_else(|| {
         eprintln!("error: no input file specified");
         process::exit(1);
     });
 
-    let output_path = output_path.unwrap_or_else(|| {
-        eprintln!("error: no output file specified (-o flag missing)");
-        process::exit(1);
-    });
-
     // Check stdlib path
-    if stdlib_path.is_none() {
-        stdlib_path = Parser::get_default_stdlib_path().ok();
-    }
 
     if let Some(path) = &stdlib_path {
+        verbose!("stdlib path: {}", path.display());
         if !path.exists() {
             eprintln!("error: Standard library not found at {}", path.display());
             eprintln!(
-                "Please install the standard library or specify correct path with --stdlib-path"
+                "Please install the standard library or specify correct path with --stdlib-path or {}",
+                STDLIB_PATH_ENV
             );
             process::exit(1);
         }
     }
 
-    // Read input file
-    let source = match fs::read_to_string(&input_path) {
-        Ok(content) => content,
-        Err(e) => {
-            eprintln!("error: couldn't read {}: {}", input_path, e);
+    // Read input file, or stdin when the input is `-`
+    let (source, input_path) = if input_path == "-" {
+        let mut content = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut content) {
+            eprintln!("error: couldn't read stdin: {}", e);
             process::exit(1);
         }
+        (content, STDIN_FILENAME.to_string())
+    } else {
+        match fs::read_to_string(&input_path) {
+            Ok(content) => (content, input_path),
+            Err(e) => {
+                eprintln!("error: couldn't read {}: {}", input_path, e);
+                process::exit(1);
+            }
+        }
+    };
+
+    let options = CompileOptions {
+        stdlib_path,
+        include_dirs,
+        lint_levels,
+        debug_assertions,
+        force_main,
+        emit_symbols,
+        error_format,
+        test,
+        target,
     };
 
+    // In check mode, only report diagnostics
+    if check_only {
+        if let Err(errors) = parse(&source, &input_path, &options) {
+            report_errors(&errors, max_errors, error_format);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let output_path = output_path.unwrap_or_else(|| {
+        eprintln!("error: no output file specified (-o flag missing)");
+        process::exit(1);
+    });
+
     // Compile
-    match compile(&source, &input_path, stdlib_path) {
-        Ok(assembly) => {
-            if let Err(e) = fs::write(&output_path, assembly) {
+    match compile(&source, &input_path, &options) {
+        Ok(compiled) => {
+            if let Err(e) = fs::write(&output_path, compiled.assembly) {
                 eprintln!("error: couldn't write to {}: {}", output_path, e);
                 process::exit(1);
             }
+
+            // Write the symbol manifest next to the assembly
+            if let Some(symbols) = compiled.symbols {
+                let symbols_path = Path::new(&output_path).with_extension("symbols.json");
+                if let Err(e) = fs::write(&symbols_path, symbols) {
+                    eprintln!("error: couldn't write to {}: {}", symbols_path.display(), e);
+                    process::exit(1);
+                }
+            }
         }
-        Err(e) => {
-            eprintln!("{}", e.format_error());
+        Err(errors) => {
+            report_errors(&errors, max_errors, error_format);
             process::exit(1);
         }
     }
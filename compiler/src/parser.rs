@@ -1,17 +1,47 @@
-use crate::ast::{AstNode, BinaryOperator};
-use crate::lexer::{ErrorKind, Lexer, Result, Token, TokenType};
-use std::collections::HashSet;
+use crate::ast::{AstNode, BinaryOperator, CallingConvention, FunctionAttributes, UnaryOperator};
+use crate::codegen::is_reserved_label;
+use crate::lexer::{
+    CompilerError, CompilerWarning, ErrorKind, Lexer, Result, SourceLocation, Token, TokenType,
+    WarningKind,
+};
+use crate::log::verbose;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+// Buffers live in the stack frame, which is 8 MiB in total by default
+const MAX_BUFFER_SIZE: i64 = 1 << 20;
+
+// The shape given to an array variable by an `array` declaration
+struct ArrayLayout {
+    element_size: usize,
+    dimensions: Vec<i64>,
+}
+
 pub struct Parser {
     lexer: Lexer,
     current_token: Token,
-    declared_functions: HashSet<String>,
+    // Declared functions and how many parameters each takes
+    declared_functions: HashMap<String, usize>,
     defined_functions: HashSet<String>,
+    // Functions defined anywhere at the top level of the file being parsed,
+    // so they can be called before their definition
+    upcoming_functions: HashMap<String, usize>,
+    // Where each function is called, for reporting calls to undefined ones
+    call_sites: HashMap<String, Vec<SourceLocation>>,
     included_files: HashSet<PathBuf>,
     current_dir: PathBuf,
     stdlib_path: Option<PathBuf>,
+    include_dirs: Vec<PathBuf>,
+    errors: Vec<CompilerError>,
+    warnings: Vec<CompilerWarning>,
+    block_depth: usize,
+    // Parameters of the function being parsed and the depth of its body
+    function_scope: Option<(Vec<String>, usize)>,
+    // Layouts declared with `array` in the function being parsed
+    array_layouts: HashMap<String, ArrayLayout>,
+    // How many loops enclose the statement being parsed
+    loop_depth: usize,
 }
 
 impl Parser {
@@ -23,11 +53,20 @@ impl Parser {
                 line: 0,
                 column: 0,
             },
-            declared_functions: HashSet::new(),
+            declared_functions: HashMap::new(),
             defined_functions: HashSet::new(),
+            upcoming_functions: HashMap::new(),
+            call_sites: HashMap::new(),
             included_files: HashSet::new(),
             current_dir: base_path.parent().unwrap_or(Path::new(".")).to_path_buf(),
             stdlib_path: std::option::Option::None,
+            include_dirs: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            block_depth: 0,
+            function_scope: None,
+            array_layouts: HashMap::new(),
+            loop_depth: 0,
         };
         parser.current_token = parser.lexer.next_token()?;
         Ok(parser)
@@ -38,6 +77,11 @@ impl Parser {
         self.stdlib_path = Some(path);
     }
 
+    // Adds a directory searched for includes not found next to the including file
+    pub fn add_include_dir(&mut self, path: PathBuf) {
+        self.include_dirs.push(path);
+    }
+
     // Modify get_stdlib_path to use custom path if set
     pub fn get_default_stdlib_path() -> std::io::Result<PathBuf> {
         let home = std::env::var("HOME")
@@ -52,21 +96,48 @@ impl Parser {
         Ok(PathBuf::from(home).join(".zed-lang/std/version/1.0.0"))
     }
 
+    // The stdlib path given with --stdlib-path, or the default location
+    fn resolve_stdlib_path(&self) -> std::io::Result<PathBuf> {
+        match &self.stdlib_path {
+            Some(path) => Ok(path.clone()),
+            None => Self::get_default_stdlib_path(),
+        }
+    }
+
+    // Resolves a non-std include relative to the including file, falling back
+    // to the -I search directories in order
+    fn resolve_local_include(&self, path: &str) -> PathBuf {
+        let relative = self.current_dir.join(path);
+        if relative.exists() {
+            return relative;
+        }
+
+        for dir in &self.include_dirs {
+            let candidate = dir.join(path);
+            if candidate.exists() {
+                verbose!("resolved {} in include directory {}", path, dir.display());
+                return candidate;
+            }
+        }
+
+        relative
+    }
+
     fn parse_include(&mut self) -> Result<Vec<AstNode>> {
         self.eat(TokenType::Include)?;
 
         // Get the string literal for the file path
-        let (full_path, _is_system_include) = match &self.current_token.token_type {
+        let (full_path, is_system_include) = match &self.current_token.token_type {
             TokenType::StringLiteral(path) => {
-                let path = path.clone();
-                self.eat(TokenType::StringLiteral(path.clone()))?;
+                let path = String::from_utf8_lossy(path).into_owned();
+                self.eat(TokenType::StringLiteral(path.clone().into_bytes()))?;
 
                 // Check if this was from a <std/...> include
-                if path.starts_with("std/") {
-                    match Self::get_default_stdlib_path() {
+                if let Some(std_path) = path.strip_prefix("std/") {
+                    match self.resolve_stdlib_path() {
                         Ok(mut stdlib_path) => {
                             // Remove "std/" prefix and just use the filename
-                            stdlib_path.push(&path[4..]); // Skip the "std/" part
+                            stdlib_path.push(std_path);
                             (stdlib_path, true)
                         }
                         Err(e) => {
@@ -77,7 +148,7 @@ impl Parser {
                         }
                     }
                 } else {
-                    (self.current_dir.join(&path), false)
+                    (self.resolve_local_include(&path), false)
                 }
             }
             _ => {
@@ -100,6 +171,8 @@ impl Parser {
             }
         };
 
+        verbose!("including {} ({})", full_path.display(), canonical_path.display());
+
         // Check for circular includes
         if !self.included_files.insert(canonical_path.clone()) {
             return Err(self.lexer.create_error(ErrorKind::SyntaxError(format!(
@@ -131,22 +204,46 @@ impl Parser {
             },
             declared_functions: self.declared_functions.clone(),
             defined_functions: self.defined_functions.clone(),
+            upcoming_functions: HashMap::new(),
+            call_sites: std::mem::take(&mut self.call_sites),
             included_files: self.included_files.clone(),
             current_dir: canonical_path
                 .parent()
                 .unwrap_or(Path::new("."))
                 .to_path_buf(),
             stdlib_path: self.stdlib_path.clone(),
+            include_dirs: self.include_dirs.clone(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            block_depth: 0,
+            function_scope: None,
+            array_layouts: HashMap::new(),
+            loop_depth: 0,
         };
         included_parser.current_token = included_parser.lexer.next_token()?;
 
         // Parse the included file
-        let nodes = included_parser.parse_program()?;
+        let mut nodes = included_parser.parse_program()?;
+
+        // Tests only run from the file that defines them, not from every
+        // file that includes it
+        for node in &mut nodes {
+            if let AstNode::FunctionDecl(_, _, _, attributes) = node {
+                attributes.test = false;
+            }
+        }
 
         // Update function tracking sets
         self.declared_functions = included_parser.declared_functions;
         self.defined_functions = included_parser.defined_functions;
+        self.call_sites = included_parser.call_sites;
         self.included_files = included_parser.included_files;
+        self.errors.extend(included_parser.errors);
+
+        // Warnings in the standard library aren't actionable for the user
+        if !is_system_include {
+            self.warnings.extend(included_parser.warnings);
+        }
 
         Ok(nodes)
     }
@@ -158,48 +255,197 @@ impl Parser {
             self.current_token = self.lexer.next_token()?;
             Ok(())
         } else {
-            Err(self
-                .lexer
-                .create_error(crate::lexer::ErrorKind::UnexpectedToken {
+            Err(self.lexer.create_token_error(
+                crate::lexer::ErrorKind::UnexpectedToken {
                     expected: expected_type.to_string(),
                     found: self.current_token.token_type.to_string(),
-                }))
+                },
+                &self.current_token,
+            ))
+        }
+    }
+
+    // Error for a keyword used where a name was expected
+    fn reserved_word_error(&self, keyword: &str) -> CompilerError {
+        self.lexer.create_token_error(
+            ErrorKind::SyntaxError(format!(
+                "'{}' is a reserved keyword and cannot be used as a name",
+                keyword
+            )),
+            &self.current_token,
+        )
+    }
+
+    // Consumes a keyword that starts a statement, catching attempts to
+    // assign to it as if it were a variable
+    fn eat_keyword(&mut self, keyword: TokenType) -> Result<()> {
+        let name = keyword.keyword().unwrap_or_default();
+        self.eat(keyword)?;
+        if self.current_token.token_type == TokenType::Assign {
+            return Err(self.reserved_word_error(name));
         }
+        Ok(())
     }
 
-    pub fn parse_program(&mut self) -> Result<Vec<AstNode>> {
+    // Expects a name, reporting keywords used in its place
+    fn expect_name(&mut self, what: &str) -> Result<String> {
+        match &self.current_token.token_type {
+            TokenType::Identifier(name) => {
+                let name = name.clone();
+                self.eat(TokenType::Identifier(name.clone()))?;
+                Ok(name)
+            }
+            token => match token.keyword() {
+                Some(keyword) => Err(self.reserved_word_error(keyword)),
+                None => Err(self.lexer.create_token_error(
+                    ErrorKind::SyntaxError(format!("expected {}", what)),
+                    &self.current_token,
+                )),
+            },
+        }
+    }
+
+    // Moves to the next token, skipping over characters the lexer rejects
+    fn advance_token(&mut self) {
+        loop {
+            match self.lexer.next_token() {
+                Ok(token) => {
+                    self.current_token = token;
+                    return;
+                }
+                Err(_) => self.lexer.skip_char(),
+            }
+        }
+    }
+
+    // Records an error and skips ahead to the end of the offending statement
+    // so that parsing can continue and report further errors
+    fn recover(&mut self, error: CompilerError) {
+        self.errors.push(error);
+
+        let mut depth = 0;
+        loop {
+            match self.current_token.token_type {
+                TokenType::EOF => return,
+                TokenType::Semicolon if depth == 0 => {
+                    self.advance_token();
+                    return;
+                }
+                TokenType::LBrace => depth += 1,
+                TokenType::RBrace => {
+                    // Leave a closing brace for the enclosing block
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                    if depth == 0 {
+                        self.advance_token();
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.advance_token();
+        }
+    }
+
+    /// Parses the whole program, returning every error encountered.
+    pub fn parse(&mut self) -> std::result::Result<Vec<AstNode>, Vec<CompilerError>> {
+        let result = self.parse_program();
+        let mut errors = std::mem::take(&mut self.errors);
+        match result {
+            Ok(statements) if errors.is_empty() => Ok(statements),
+            Ok(_) => Err(errors),
+            Err(e) => {
+                errors.push(e);
+                Err(errors)
+            }
+        }
+    }
+
+    /// Returns the canonical paths of every file included so far, sorted.
+    pub fn included_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.included_files.iter().cloned().collect();
+        files.sort();
+        files
+    }
+
+    /// Returns the warnings collected so far, including those from includes.
+    pub fn take_warnings(&mut self) -> Vec<CompilerWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    // Warns when a parameter is assigned inside a block nested in the
+    // function body. Blocks don't introduce scopes, so this changes the
+    // parameter itself rather than declaring a new local
+    fn check_shadowed_parameter(&mut self, name: &str, line: usize, column: usize) {
+        let Some((parameters, body_depth)) = &self.function_scope else {
+            return;
+        };
+
+        if self.block_depth > *body_depth && parameters.iter().any(|param| param == name) {
+            let warning = self.lexer.create_warning(
+                WarningKind::ShadowedParameter,
+                format!(
+                    "assigns to parameter `{}` inside a nested block; this modifies the parameter",
+                    name
+                ),
+                line,
+                column,
+            );
+            self.warnings.push(warning);
+        }
+    }
+
+    fn parse_program(&mut self) -> Result<Vec<AstNode>> {
+        self.upcoming_functions = self.lexer.scan_function_definitions();
         let mut statements = Vec::new();
         while self.current_token.token_type != TokenType::EOF {
-            match self.current_token.token_type {
+            let result = match self.current_token.token_type {
                 TokenType::Include => {
                     // Handle include directive
-                    let included_nodes = self.parse_include()?;
-                    statements.extend(included_nodes);
+                    self.parse_include().map(|included_nodes| statements.extend(included_nodes))
                 }
-                _ => {
-                    let statement = self.parse_statement()?;
-                    statements.push(statement);
+                _ => self.parse_statement().map(|statement| statements.push(statement)),
+            };
+
+            if let Err(e) = result {
+                self.recover(e);
+
+                // A stray closing brace can't start a statement; skip it
+                if self.current_token.token_type == TokenType::RBrace {
+                    self.advance_token();
                 }
             }
         }
 
         // Verify all declared functions are defined
-        for func_name in &self.declared_functions {
-            if !self.defined_functions.contains(func_name) {
-                return Err(self
-                    .lexer
-                    .create_error(crate::lexer::ErrorKind::SyntaxError(format!(
-                        "function '{}' declared but not defined",
-                        func_name
-                    ))));
+        let mut undefined: Vec<&String> = self
+            .declared_functions
+            .keys()
+            .filter(|func_name| !self.defined_functions.contains(*func_name))
+            .collect();
+        undefined.sort();
+        for func_name in undefined {
+            let mut message = format!("function '{}' declared but not defined", func_name);
+            if let Some(sites) = self.call_sites.get(func_name) {
+                let sites: Vec<String> = sites
+                    .iter()
+                    .map(|site| format!("{}:{}:{}", site.file, site.line, site.column))
+                    .collect();
+                message.push_str(&format!(" (called at {})", sites.join(", ")));
             }
+            self.errors.push(
+                self.lexer
+                    .create_error(crate::lexer::ErrorKind::SyntaxError(message)),
+            );
         }
 
         Ok(statements)
     }
 
     fn parse_inline_asm(&mut self) -> Result<AstNode> {
-        self.eat(TokenType::Asm)?;
+        self.eat_keyword(TokenType::Asm)?;
 
         // Skip any whitespace after 'asm'
         self.lexer.skip_whitespace_and_comments();
@@ -207,8 +453,8 @@ impl Parser {
         // Parse template string
         let template = match &self.current_token.token_type {
             TokenType::StringLiteral(s) => {
-                let s = s.clone();
-                self.eat(TokenType::StringLiteral(s.clone()))?;
+                let s = String::from_utf8_lossy(s).into_owned();
+                self.eat(TokenType::StringLiteral(s.clone().into_bytes()))?;
                 s
             }
             _ => {
@@ -240,8 +486,8 @@ impl Parser {
                 // Parse constraint
                 let constraint = match &self.current_token.token_type {
                     TokenType::StringLiteral(s) => {
-                        let s = s.clone();
-                        self.eat(TokenType::StringLiteral(s.clone()))?;
+                        let s = String::from_utf8_lossy(s).into_owned();
+                        self.eat(TokenType::StringLiteral(s.clone().into_bytes()))?;
                         s
                     }
                     _ => {
@@ -288,8 +534,8 @@ impl Parser {
                     // Parse constraint
                     let constraint = match &self.current_token.token_type {
                         TokenType::StringLiteral(s) => {
-                            let s = s.clone();
-                            self.eat(TokenType::StringLiteral(s.clone()))?;
+                            let s = String::from_utf8_lossy(s).into_owned();
+                            self.eat(TokenType::StringLiteral(s.clone().into_bytes()))?;
                             s
                         }
                         _ => {
@@ -334,8 +580,8 @@ impl Parser {
 
                     match &self.current_token.token_type {
                         TokenType::StringLiteral(s) => {
-                            let s = s.clone();
-                            self.eat(TokenType::StringLiteral(s.clone()))?;
+                            let s = String::from_utf8_lossy(s).into_owned();
+                            self.eat(TokenType::StringLiteral(s.clone().into_bytes()))?;
                             clobbers.push(s);
                         }
                         _ => {
@@ -363,23 +609,40 @@ impl Parser {
 
     fn parse_statement(&mut self) -> Result<AstNode> {
         match &self.current_token.token_type {
-            TokenType::Function => self.parse_function_declaration(),
+            TokenType::Function => self.parse_function_declaration(FunctionAttributes::default()),
+            TokenType::CConv | TokenType::Test => self.parse_function_attributes(),
             TokenType::Return => self.parse_return_statement(),
+            TokenType::Break => self.parse_loop_jump(TokenType::Break, AstNode::Break),
+            TokenType::Continue => self.parse_loop_jump(TokenType::Continue, AstNode::Continue),
             TokenType::If => self.parse_if_statement(),
+            // A matched `else` is consumed by parse_if_statement, so this
+            // one follows something other than an if's branch
+            TokenType::Else => Err(self.lexer.create_token_error(
+                ErrorKind::SyntaxError("'else' without a matching 'if'".to_string()),
+                &self.current_token,
+            )),
             TokenType::While => self.parse_while_statement(),
+            TokenType::Do => self.parse_do_while_statement(),
+            TokenType::For => self.parse_for_statement(),
+            TokenType::Array => self.parse_array_declaration(),
             TokenType::LBrace => self.parse_block(),
             TokenType::Asm => self.parse_inline_asm(),
             TokenType::Align => self.parse_align_directive(),
+            TokenType::Identifier(name)
+                if name == "assert" && !self.is_function_declared("assert") =>
+            {
+                self.parse_assert()
+            }
             _ => {
                 let expr = self.parse_expression()?;
                 self.eat(TokenType::Semicolon)?;
-                Ok(expr)
+                Ok(AstNode::ExprStmt(Box::new(expr)))
             }
         }
     }
 
     fn parse_if_statement(&mut self) -> Result<AstNode> {
-        self.eat(TokenType::If)?;
+        self.eat_keyword(TokenType::If)?;
         self.eat(TokenType::LParen)?;
         let condition = self.parse_expression()?;
         self.eat(TokenType::RParen)?;
@@ -397,21 +660,88 @@ impl Parser {
     }
 
     fn parse_while_statement(&mut self) -> Result<AstNode> {
-        self.eat(TokenType::While)?;
+        self.eat_keyword(TokenType::While)?;
         self.eat(TokenType::LParen)?;
         let condition = self.parse_expression()?;
         self.eat(TokenType::RParen)?;
-        let body = self.parse_statement()?;
+        let body = self.parse_loop_body()?;
         Ok(AstNode::While(Box::new(condition), Box::new(body)))
     }
 
+    fn parse_do_while_statement(&mut self) -> Result<AstNode> {
+        self.eat_keyword(TokenType::Do)?;
+        let body = self.parse_loop_body()?;
+        self.eat_keyword(TokenType::While)?;
+        self.eat(TokenType::LParen)?;
+        let condition = self.parse_expression()?;
+        self.eat(TokenType::RParen)?;
+        self.eat(TokenType::Semicolon)?;
+        Ok(AstNode::DoWhile(Box::new(body), Box::new(condition)))
+    }
+
+    // `for (init; condition; update) body`, where any of the three clauses
+    // may be left empty
+    fn parse_for_statement(&mut self) -> Result<AstNode> {
+        self.eat_keyword(TokenType::For)?;
+        self.eat(TokenType::LParen)?;
+        let init = self.parse_for_clause(TokenType::Semicolon)?;
+        self.eat(TokenType::Semicolon)?;
+        let condition = self.parse_for_clause(TokenType::Semicolon)?;
+        self.eat(TokenType::Semicolon)?;
+        let update = self.parse_for_clause(TokenType::RParen)?;
+        self.eat(TokenType::RParen)?;
+        let body = self.parse_loop_body()?;
+
+        Ok(AstNode::For {
+            init: init.map(|init| Box::new(AstNode::ExprStmt(Box::new(init)))),
+            condition: condition.map(Box::new),
+            update: update.map(|update| Box::new(AstNode::ExprStmt(Box::new(update)))),
+            body: Box::new(body),
+        })
+    }
+
+    fn parse_loop_body(&mut self) -> Result<AstNode> {
+        self.loop_depth += 1;
+        let body = self.parse_statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    // `break;` or `continue;`, which only make sense inside a loop
+    fn parse_loop_jump(&mut self, keyword: TokenType, node: AstNode) -> Result<AstNode> {
+        if self.loop_depth == 0 {
+            return Err(self.lexer.create_token_error(
+                ErrorKind::SyntaxError(format!("'{}' outside of a loop", keyword)),
+                &self.current_token,
+            ));
+        }
+        self.eat_keyword(keyword)?;
+        self.eat(TokenType::Semicolon)?;
+        Ok(node)
+    }
+
+    fn parse_for_clause(&mut self, terminator: TokenType) -> Result<Option<AstNode>> {
+        if self.current_token.token_type == terminator {
+            Ok(None)
+        } else {
+            self.parse_expression().map(Some)
+        }
+    }
+
     fn parse_block(&mut self) -> Result<AstNode> {
         self.eat(TokenType::LBrace)?;
         let mut statements = Vec::new();
 
-        while self.current_token.token_type != TokenType::RBrace {
-            statements.push(self.parse_statement()?);
+        self.block_depth += 1;
+        while self.current_token.token_type != TokenType::RBrace
+            && self.current_token.token_type != TokenType::EOF
+        {
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(e) => self.recover(e),
+            }
         }
+        self.block_depth -= 1;
 
         self.eat(TokenType::RBrace)?;
         Ok(AstNode::Block(statements))
@@ -422,19 +752,21 @@ impl Parser {
     }
 
     fn parse_assignment(&mut self) -> Result<AstNode> {
-        let mut expr = self.parse_logical()?;
+        let (line, column) = (self.current_token.line, self.current_token.column);
+        let mut expr = self.parse_conditional()?;
 
         if let TokenType::Assign = self.current_token.token_type {
             match expr {
                 AstNode::Variable(name) => {
+                    self.check_shadowed_parameter(&name, line, column);
                     self.eat(TokenType::Assign)?;
                     let value = self.parse_assignment()?;
                     expr = AstNode::Assignment(name, Box::new(value));
                 }
-                AstNode::ArrayIndex(array, index) => {
+                AstNode::ArrayIndex(array, index, element_size) => {
                     self.eat(TokenType::Assign)?;
                     let value = self.parse_assignment()?;
-                    expr = AstNode::ArrayAssignment(array, index, Box::new(value));
+                    expr = AstNode::ArrayAssignment(array, index, Box::new(value), element_size);
                 }
                 _ => {
                     return Err(self.lexer.create_error(ErrorKind::SyntaxError(
@@ -477,8 +809,10 @@ impl Parser {
         Ok(AstNode::Align(alignment, Box::new(node)))
     }
 
-    fn parse_comparison(&mut self) -> Result<AstNode> {
-        let mut expr = self.parse_additive()?;
+    // Equality binds looser than ordering, so `a < b == c < d` compares
+    // the two results
+    fn parse_equality(&mut self) -> Result<AstNode> {
+        let mut expr = self.parse_relational()?;
 
         loop {
             let op = match &self.current_token.token_type {
@@ -490,6 +824,21 @@ impl Parser {
                     self.eat(TokenType::NotEquals)?;
                     BinaryOperator::NotEquals
                 }
+                _ => break,
+            };
+
+            let right = self.parse_relational()?;
+            expr = AstNode::BinaryOp(Box::new(expr), op, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_relational(&mut self) -> Result<AstNode> {
+        let mut expr = self.parse_bitwise_or()?;
+
+        loop {
+            let op = match &self.current_token.token_type {
                 TokenType::Less => {
                     self.eat(TokenType::Less)?;
                     BinaryOperator::Less
@@ -509,31 +858,53 @@ impl Parser {
                 _ => break,
             };
 
-            let right = self.parse_additive()?;
+            let right = self.parse_bitwise_or()?;
             expr = AstNode::BinaryOp(Box::new(expr), op, Box::new(right));
         }
 
         Ok(expr)
     }
 
-    fn parse_logical(&mut self) -> Result<AstNode> {
-        let mut expr = self.parse_comparison()?;
+    // `?:` groups to the right, so `a ? b : c ? d : e` is `a ? b : (c ? d : e)`
+    fn parse_conditional(&mut self) -> Result<AstNode> {
+        let condition = self.parse_logical_or()?;
 
-        loop {
-            let op = match &self.current_token.token_type {
-                TokenType::And => {
-                    self.eat(TokenType::And)?;
-                    BinaryOperator::And
-                }
-                TokenType::Or => {
-                    self.eat(TokenType::Or)?;
-                    BinaryOperator::Or
-                }
-                _ => break,
-            };
+        if self.current_token.token_type != TokenType::Question {
+            return Ok(condition);
+        }
 
-            let right = self.parse_comparison()?;
-            expr = AstNode::BinaryOp(Box::new(expr), op, Box::new(right));
+        self.eat(TokenType::Question)?;
+        let then_expr = self.parse_conditional()?;
+        self.eat(TokenType::Colon)?;
+        let else_expr = self.parse_conditional()?;
+
+        Ok(AstNode::Ternary(
+            Box::new(condition),
+            Box::new(then_expr),
+            Box::new(else_expr),
+        ))
+    }
+
+    fn parse_logical_or(&mut self) -> Result<AstNode> {
+        let mut expr = self.parse_logical_and()?;
+
+        while let TokenType::Or = self.current_token.token_type {
+            self.eat(TokenType::Or)?;
+            let right = self.parse_logical_and()?;
+            expr = AstNode::BinaryOp(Box::new(expr), BinaryOperator::Or, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    // && binds tighter than ||, so `a || b && c` is `a || (b && c)`
+    fn parse_logical_and(&mut self) -> Result<AstNode> {
+        let mut expr = self.parse_equality()?;
+
+        while let TokenType::And = self.current_token.token_type {
+            self.eat(TokenType::And)?;
+            let right = self.parse_equality()?;
+            expr = AstNode::BinaryOp(Box::new(expr), BinaryOperator::And, Box::new(right));
         }
 
         Ok(expr)
@@ -562,8 +933,69 @@ impl Parser {
         Ok(expr)
     }
 
+    // Bitwise operators bind tighter than comparisons, so `flags & MASK == 0`
+    // tests the masked value. From loosest to tightest: |, ^, &, then shifts.
+    fn parse_bitwise_or(&mut self) -> Result<AstNode> {
+        let mut expr = self.parse_bitwise_xor()?;
+
+        while let TokenType::BitOr = self.current_token.token_type {
+            self.eat(TokenType::BitOr)?;
+            let right = self.parse_bitwise_xor()?;
+            expr = AstNode::BinaryOp(Box::new(expr), BinaryOperator::BitOr, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_bitwise_xor(&mut self) -> Result<AstNode> {
+        let mut expr = self.parse_bitwise_and()?;
+
+        while let TokenType::BitXor = self.current_token.token_type {
+            self.eat(TokenType::BitXor)?;
+            let right = self.parse_bitwise_and()?;
+            expr = AstNode::BinaryOp(Box::new(expr), BinaryOperator::BitXor, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_bitwise_and(&mut self) -> Result<AstNode> {
+        let mut expr = self.parse_shift()?;
+
+        while let TokenType::BitAnd = self.current_token.token_type {
+            self.eat(TokenType::BitAnd)?;
+            let right = self.parse_shift()?;
+            expr = AstNode::BinaryOp(Box::new(expr), BinaryOperator::BitAnd, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_shift(&mut self) -> Result<AstNode> {
+        let mut expr = self.parse_additive()?;
+
+        loop {
+            let op = match &self.current_token.token_type {
+                TokenType::ShiftLeft => {
+                    self.eat(TokenType::ShiftLeft)?;
+                    BinaryOperator::ShiftLeft
+                }
+                TokenType::ShiftRight => {
+                    self.eat(TokenType::ShiftRight)?;
+                    BinaryOperator::ShiftRight
+                }
+                _ => break,
+            };
+
+            let right = self.parse_additive()?;
+            expr = AstNode::BinaryOp(Box::new(expr), op, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
     fn parse_multiplicative(&mut self) -> Result<AstNode> {
-        let mut expr = self.parse_primary()?;
+        let mut expr = self.parse_unary()?;
 
         loop {
             let op = match &self.current_token.token_type {
@@ -575,43 +1007,141 @@ impl Parser {
                     self.eat(TokenType::Divide)?;
                     BinaryOperator::Divide
                 }
+                TokenType::Modulo => {
+                    self.eat(TokenType::Modulo)?;
+                    BinaryOperator::Modulo
+                }
                 _ => break,
             };
 
-            let right = self.parse_primary()?;
+            let right = self.parse_unary()?;
             expr = AstNode::BinaryOp(Box::new(expr), op, Box::new(right));
         }
 
         Ok(expr)
     }
 
+    fn parse_unary(&mut self) -> Result<AstNode> {
+        match self.current_token.token_type {
+            TokenType::BitNot => {
+                self.eat(TokenType::BitNot)?;
+                let operand = self.parse_unary()?;
+                Ok(AstNode::UnaryOp(UnaryOperator::BitwiseNot, Box::new(operand)))
+            }
+            TokenType::BitAnd => {
+                self.eat(TokenType::BitAnd)?;
+                let operand = self.parse_unary()?;
+                if !matches!(operand, AstNode::ArrayIndex(..)) {
+                    return Err(self.lexer.create_error(ErrorKind::SyntaxError(
+                        "'&' can only take the address of an array element".to_string(),
+                    )));
+                }
+                Ok(AstNode::AddressOf(Box::new(operand)))
+            }
+            TokenType::Minus => {
+                self.eat(TokenType::Minus)?;
+                let operand = self.parse_unary()?;
+                Ok(AstNode::UnaryOp(UnaryOperator::Negate, Box::new(operand)))
+            }
+            TokenType::Not => {
+                self.eat(TokenType::Not)?;
+                let operand = self.parse_unary()?;
+                Ok(AstNode::UnaryOp(UnaryOperator::Not, Box::new(operand)))
+            }
+            _ => self.parse_primary(),
+        }
+    }
+
     fn is_function_declared(&self, name: &str) -> bool {
-        self.declared_functions.contains(name)
+        self.declared_functions.contains_key(name) || self.upcoming_functions.contains_key(name)
+    }
+
+    // How many arguments a call to `name` has to pass
+    fn parameter_count(&self, name: &str) -> Option<usize> {
+        self.declared_functions
+            .get(name)
+            .or_else(|| self.upcoming_functions.get(name))
+            .copied()
     }
 
     fn is_function_defined(&self, name: &str) -> bool {
         self.defined_functions.contains(name)
     }
 
-    fn parse_function_declaration(&mut self) -> Result<AstNode> {
-        self.eat(TokenType::Function)?;
+    // Attributes such as `@test` or `@cconv("sysv")` apply to the function
+    // declared after them
+    fn parse_function_attributes(&mut self) -> Result<AstNode> {
+        let mut attributes = FunctionAttributes::default();
 
-        // Parse function name
-        let name = match &self.current_token.token_type {
-            TokenType::Identifier(name) => {
-                let name = name.clone();
-                self.eat(TokenType::Identifier(name.clone()))?;
-                name
+        loop {
+            match self.current_token.token_type {
+                TokenType::CConv => attributes.cconv = self.parse_cconv_attribute()?,
+                TokenType::Test => {
+                    self.eat(TokenType::Test)?;
+                    attributes.test = true;
+                }
+                TokenType::Function => break,
+                _ => {
+                    return Err(self.lexer.create_error(ErrorKind::SyntaxError(
+                        "attributes must be followed by a function declaration".to_string(),
+                    )))
+                }
             }
-            _ => {
-                return Err(self
-                    .lexer
-                    .create_error(crate::lexer::ErrorKind::SyntaxError(
-                        "expected function name".to_string(),
+        }
+
+        self.parse_function_declaration(attributes)
+    }
+
+    // `@cconv("name")` picks the calling convention of the function
+    fn parse_cconv_attribute(&mut self) -> Result<CallingConvention> {
+        self.eat(TokenType::CConv)?;
+        self.eat(TokenType::LParen)?;
+
+        let cconv = match &self.current_token.token_type {
+            TokenType::StringLiteral(name) => {
+                let name = String::from_utf8_lossy(name).into_owned();
+                let cconv = CallingConvention::from_name(&name).ok_or_else(|| {
+                    let supported: Vec<&str> =
+                        CallingConvention::ALL.iter().map(|c| c.name()).collect();
+                    self.lexer.create_error(ErrorKind::SyntaxError(format!(
+                        "unknown calling convention '{}' (supported: {})",
+                        name,
+                        supported.join(", ")
                     )))
+                })?;
+                self.eat(TokenType::StringLiteral(name.into_bytes()))?;
+                cconv
+            }
+            _ => {
+                return Err(self.lexer.create_error(ErrorKind::SyntaxError(
+                    "expected calling convention name as a string".to_string(),
+                )))
             }
         };
 
+        self.eat(TokenType::RParen)?;
+        Ok(cconv)
+    }
+
+    fn parse_function_declaration(&mut self, attributes: FunctionAttributes) -> Result<AstNode> {
+        self.eat_keyword(TokenType::Function)?;
+
+        if let TokenType::Identifier(name) = &self.current_token.token_type {
+            if is_reserved_label(name) {
+                return Err(self.lexer.create_token_error(
+                    ErrorKind::SyntaxError(format!(
+                        "'{}' is reserved for code generated by the compiler and can't name a function",
+                        name
+                    )),
+                    &self.current_token,
+                ));
+            }
+        }
+
+        // Parse function name
+        let name_token = self.current_token.clone();
+        let name = self.expect_name("function name")?;
+
         // Check if function is already defined
         if self.is_function_defined(&name) {
             return Err(self
@@ -626,41 +1156,237 @@ impl Parser {
         self.eat(TokenType::LParen)?;
         let mut parameters = Vec::new();
 
-        if let TokenType::Identifier(_) = &self.current_token.token_type {
-            if let TokenType::Identifier(param) = self.current_token.token_type.clone() {
-                parameters.push(param.clone());
-                self.eat(TokenType::Identifier(param))?;
+        if self.current_token.token_type != TokenType::RParen {
+            parameters.push(self.expect_name("parameter name")?);
 
-                while let TokenType::Comma = self.current_token.token_type {
-                    self.eat(TokenType::Comma)?;
-                    if let TokenType::Identifier(param) = self.current_token.token_type.clone() {
-                        parameters.push(param.clone());
-                        self.eat(TokenType::Identifier(param))?;
-                    }
-                }
+            while let TokenType::Comma = self.current_token.token_type {
+                self.eat(TokenType::Comma)?;
+                parameters.push(self.expect_name("parameter name")?);
             }
         }
 
         self.eat(TokenType::RParen)?;
 
+        // The test runner calls tests without arguments
+        if attributes.test && !parameters.is_empty() {
+            return Err(self.lexer.create_error(ErrorKind::SyntaxError(format!(
+                "test function '{}' can't take parameters",
+                name
+            ))));
+        }
+
+        // A predeclaration and the definition have to agree, or calls
+        // checked against one would be wrong for the other
+        if let Some(&declared) = self.declared_functions.get(&name) {
+            if declared != parameters.len() {
+                return Err(self.lexer.create_span_error(
+                    ErrorKind::SyntaxError(format!(
+                        "function '{}' was declared with {} but has {} here",
+                        name,
+                        count_of(declared, "parameter"),
+                        parameters.len()
+                    )),
+                    name_token.line,
+                    name_token.column,
+                ));
+            }
+        }
+
         // Check if this is a predeclaration
         if self.current_token.token_type == TokenType::Semicolon {
             self.eat(TokenType::Semicolon)?;
-            self.declared_functions.insert(name.clone());
+            self.declared_functions.insert(name.clone(), parameters.len());
             return Ok(AstNode::FunctionPredecl(name, parameters));
         }
 
         // Parse function body
-        let body = self.parse_block()?;
+        let enclosing_scope = self
+            .function_scope
+            .replace((parameters.clone(), self.block_depth + 1));
+        let enclosing_layouts = std::mem::take(&mut self.array_layouts);
+        let enclosing_loop_depth = std::mem::take(&mut self.loop_depth);
+        let body = self.parse_block();
+        self.function_scope = enclosing_scope;
+        self.array_layouts = enclosing_layouts;
+        self.loop_depth = enclosing_loop_depth;
+        let body = body?;
 
         // Add to defined functions set
         self.defined_functions.insert(name.clone());
-        self.declared_functions.insert(name.clone());
+        self.declared_functions.insert(name.clone(), parameters.len());
+
+        Ok(AstNode::FunctionDecl(name, parameters, Box::new(body), attributes))
+    }
+
+    // `assert(condition);` is built in. The condition's source text and
+    // location are kept so a failure can report them.
+    fn parse_assert(&mut self) -> Result<AstNode> {
+        let line = self.current_token.line;
+        self.eat(TokenType::Identifier("assert".to_string()))?;
+        self.eat(TokenType::LParen)?;
+
+        let start = (self.current_token.line, self.current_token.column);
+        let condition = self.parse_expression()?;
+        let end = (self.current_token.line, self.current_token.column);
+
+        self.eat(TokenType::RParen)?;
+        self.eat(TokenType::Semicolon)?;
+
+        let message = format!(
+            "{}:{}: assertion failed: {}",
+            self.lexer.filename(),
+            line,
+            self.lexer.source_text(start, end)
+        );
+        Ok(AstNode::Assert(Box::new(condition), message))
+    }
+
+    // `array name:SIZE[D1][D2]...;` declares how `name` is laid out: elements
+    // of SIZE bytes (1 if omitted) stored in row-major order. Nothing is
+    // allocated; `name` still holds the address of the first element.
+    fn parse_array_declaration(&mut self) -> Result<AstNode> {
+        self.eat_keyword(TokenType::Array)?;
+        let name = self.expect_name("array name")?;
+
+        let mut element_size = 1;
+        if self.current_token.token_type == TokenType::Colon {
+            self.eat(TokenType::Colon)?;
+            element_size = match self.current_token.token_type {
+                TokenType::Number(size @ (1 | 2 | 4 | 8)) => {
+                    self.eat(TokenType::Number(size))?;
+                    size as usize
+                }
+                _ => {
+                    return Err(self.lexer.create_token_error(
+                        ErrorKind::SyntaxError(
+                            "array element size must be 1, 2, 4 or 8".to_string(),
+                        ),
+                        &self.current_token,
+                    ))
+                }
+            };
+        }
 
-        Ok(AstNode::FunctionDecl(name, parameters, Box::new(body)))
+        let mut dimensions = Vec::new();
+        while self.current_token.token_type == TokenType::LeftBracket {
+            self.eat(TokenType::LeftBracket)?;
+            match self.current_token.token_type {
+                TokenType::Number(length) if length > 0 => {
+                    self.eat(TokenType::Number(length))?;
+                    dimensions.push(length);
+                }
+                _ => {
+                    return Err(self.lexer.create_token_error(
+                        ErrorKind::SyntaxError(
+                            "array dimensions must be positive numbers".to_string(),
+                        ),
+                        &self.current_token,
+                    ))
+                }
+            }
+            self.eat(TokenType::RightBracket)?;
+        }
+        if dimensions.is_empty() {
+            return Err(self.lexer.create_token_error(
+                ErrorKind::SyntaxError(format!("expected dimensions for array '{}'", name)),
+                &self.current_token,
+            ));
+        }
+        self.eat(TokenType::Semicolon)?;
+
+        self.array_layouts.insert(
+            name,
+            ArrayLayout {
+                element_size,
+                dimensions,
+            },
+        );
+        Ok(AstNode::Block(Vec::new()))
     }
 
-    fn parse_function_call(&mut self, name: String) -> Result<AstNode> {
+    // Parses the `[i][j]...` after an array name into a single element
+    // access. Arrays without an `array` declaration are byte arrays with one
+    // dimension of unknown length; declared ones check each index against
+    // its dimension and fold them into a row-major offset.
+    fn parse_array_index(&mut self, name: String) -> Result<AstNode> {
+        let mut indices = Vec::new();
+        while self.current_token.token_type == TokenType::LeftBracket {
+            self.eat(TokenType::LeftBracket)?;
+            indices.push(self.parse_expression()?);
+            self.eat(TokenType::RightBracket)?;
+        }
+
+        let (element_size, dimensions) = match self.array_layouts.get(&name) {
+            Some(layout) => (layout.element_size, layout.dimensions.as_slice()),
+            None => (1, &[0][..]),
+        };
+        if indices.len() != dimensions.len() {
+            let message = if self.array_layouts.contains_key(&name) {
+                format!(
+                    "array '{}' has {} dimension(s) but is indexed with {}",
+                    name,
+                    dimensions.len(),
+                    indices.len()
+                )
+            } else {
+                format!(
+                    "'{}' needs an `array` declaration to be indexed with {} indices",
+                    name,
+                    indices.len()
+                )
+            };
+            return Err(self.lexer.create_error(ErrorKind::SyntaxError(message)));
+        }
+
+        let declared = self.array_layouts.contains_key(&name);
+        let mut indices = indices.into_iter().zip(dimensions).map(|(index, &length)| {
+            if declared {
+                AstNode::IndexCheck(Box::new(index), length)
+            } else {
+                index
+            }
+        });
+        let mut offset = indices.next().expect("at least one index");
+        for (index, &length) in indices.zip(&dimensions[1..]) {
+            let row = AstNode::BinaryOp(
+                Box::new(offset),
+                BinaryOperator::Multiply,
+                Box::new(AstNode::Number(length)),
+            );
+            offset = AstNode::BinaryOp(Box::new(row), BinaryOperator::Add, Box::new(index));
+        }
+
+        Ok(AstNode::ArrayIndex(
+            Box::new(AstNode::Variable(name)),
+            Box::new(offset),
+            element_size,
+        ))
+    }
+
+    // `buffer(size)` reserves `size` bytes in the current frame and evaluates
+    // to their address
+    fn parse_buffer(&mut self) -> Result<AstNode> {
+        self.eat(TokenType::LParen)?;
+        let size = match self.current_token.token_type {
+            TokenType::Number(size) if size > 0 && size <= MAX_BUFFER_SIZE => {
+                self.eat(TokenType::Number(size))?;
+                size
+            }
+            _ => {
+                return Err(self.lexer.create_token_error(
+                    ErrorKind::SyntaxError(format!(
+                        "buffer size must be a number from 1 to {}",
+                        MAX_BUFFER_SIZE
+                    )),
+                    &self.current_token,
+                ))
+            }
+        };
+        self.eat(TokenType::RParen)?;
+        Ok(AstNode::Buffer(size as usize))
+    }
+
+    fn parse_function_call(&mut self, name: String, location: SourceLocation) -> Result<AstNode> {
         // Check if function is declared
         if !self.is_function_declared(&name) {
             return Err(self
@@ -684,6 +1410,24 @@ impl Parser {
         }
 
         self.eat(TokenType::RParen)?;
+
+        if let Some(expected) = self.parameter_count(&name) {
+            if arguments.len() != expected {
+                return Err(self.lexer.create_span_error(
+                    ErrorKind::SyntaxError(format!(
+                        "function '{}' takes {} but {} {} given",
+                        name,
+                        count_of(expected, "argument"),
+                        arguments.len(),
+                        if arguments.len() == 1 { "was" } else { "were" }
+                    )),
+                    location.line,
+                    location.column,
+                ));
+            }
+        }
+
+        self.call_sites.entry(name.clone()).or_default().push(location);
         Ok(AstNode::FunctionCall(name, arguments))
     }
 
@@ -695,6 +1439,14 @@ impl Parser {
                 self.eat(TokenType::Number(value))?;
                 Ok(AstNode::Number(value))
             }
+            TokenType::True => {
+                self.eat_keyword(TokenType::True)?;
+                Ok(AstNode::Number(1))
+            }
+            TokenType::False => {
+                self.eat_keyword(TokenType::False)?;
+                Ok(AstNode::Number(0))
+            }
             TokenType::StringLiteral(s) => {
                 let value = s.clone();
                 self.eat(TokenType::StringLiteral(value.clone()))?;
@@ -702,18 +1454,19 @@ impl Parser {
             }
             TokenType::Identifier(name) => {
                 let name = name.clone();
+                let location = self
+                    .lexer
+                    .location(self.current_token.line, self.current_token.column);
                 self.eat(TokenType::Identifier(name.clone()))?;
                 // Check if this is an array index
                 if self.current_token.token_type == TokenType::LeftBracket {
-                    self.eat(TokenType::LeftBracket)?;
-                    let index = self.parse_expression()?;
-                    self.eat(TokenType::RightBracket)?;
-                    Ok(AstNode::ArrayIndex(
-                        Box::new(AstNode::Variable(name)),
-                        Box::new(index),
-                    ))
+                    self.parse_array_index(name)
                 } else if self.current_token.token_type == TokenType::LParen {
-                    self.parse_function_call(name)
+                    if name == "buffer" && !self.is_function_declared("buffer") {
+                        self.parse_buffer()
+                    } else {
+                        self.parse_function_call(name, location)
+                    }
                 } else {
                     Ok(AstNode::Variable(name))
                 }
@@ -724,17 +1477,21 @@ impl Parser {
                 self.eat(TokenType::RParen)?;
                 Ok(expr)
             }
-            _ => Err(self
-                .lexer
-                .create_error(crate::lexer::ErrorKind::SyntaxError(format!(
-                    "unexpected token in expression: {}",
-                    self.current_token.token_type
-                )))),
+            token => match token.keyword() {
+                Some(keyword) => Err(self.reserved_word_error(keyword)),
+                None => Err(self.lexer.create_token_error(
+                    crate::lexer::ErrorKind::SyntaxError(format!(
+                        "unexpected token in expression: {}",
+                        self.current_token.token_type
+                    )),
+                    &self.current_token,
+                )),
+            },
         }
     }
 
     fn parse_return_statement(&mut self) -> Result<AstNode> {
-        self.eat(TokenType::Return)?;
+        self.eat_keyword(TokenType::Return)?;
 
         let value = if self.current_token.token_type != TokenType::Semicolon {
             Some(Box::new(self.parse_expression()?))
@@ -746,3 +1503,12 @@ impl Parser {
         Ok(AstNode::Return(value))
     }
 }
+
+// "1 parameter", "2 parameters"
+fn count_of(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
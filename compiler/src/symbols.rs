@@ -0,0 +1,81 @@
+use crate::ast::AstNode;
+use crate::codegen::Target;
+
+// Symbols defined, declared, and referenced by a single compilation unit
+pub struct SymbolManifest {
+    defined: Vec<(String, bool)>, // (name, is global)
+    declared: Vec<String>,
+    called: Vec<String>,
+}
+
+fn json_array(items: &[String]) -> String {
+    if items.is_empty() {
+        "[]".to_string()
+    } else {
+        format!("[\n{}\n  ]", items.join(",\n"))
+    }
+}
+
+fn push_unique(list: &mut Vec<String>, name: &str) {
+    if !list.iter().any(|existing| existing == name) {
+        list.push(name.to_string());
+    }
+}
+
+impl SymbolManifest {
+    pub fn new(ast: &[AstNode], is_main_file: bool, target: Target) -> Self {
+        let mut manifest = SymbolManifest {
+            defined: Vec::new(),
+            declared: Vec::new(),
+            called: Vec::new(),
+        };
+
+        for node in ast {
+            manifest.collect(node);
+        }
+
+        // Only the entry point is exported; functions are local to the unit
+        if is_main_file {
+            manifest.defined.push((target.entry_symbol().to_string(), true));
+        }
+
+        // Predeclared functions that are defined here aren't external
+        let defined = &manifest.defined;
+        manifest
+            .declared
+            .retain(|name| !defined.iter().any(|(defined_name, _)| defined_name == name));
+
+        manifest
+    }
+
+    fn collect(&mut self, node: &AstNode) {
+        node.walk(&mut |node| match node {
+            AstNode::FunctionDecl(name, _, _, _)
+                if !self.defined.iter().any(|(defined_name, _)| defined_name == name) =>
+            {
+                self.defined.push((name.clone(), false));
+            }
+            AstNode::FunctionPredecl(name, _) => push_unique(&mut self.declared, name),
+            AstNode::FunctionCall(name, _) => push_unique(&mut self.called, name),
+            _ => {}
+        });
+    }
+
+    pub fn to_json(&self) -> String {
+        let defined: Vec<String> = self
+            .defined
+            .iter()
+            .map(|(name, global)| format!("    {{ \"name\": \"{}\", \"global\": {} }}", name, global))
+            .collect();
+        let quote = |names: &[String]| -> Vec<String> {
+            names.iter().map(|name| format!("    \"{}\"", name)).collect()
+        };
+
+        format!(
+            "{{\n  \"defined\": {},\n  \"extern\": {},\n  \"called\": {}\n}}\n",
+            json_array(&defined),
+            json_array(&quote(&self.declared)),
+            json_array(&quote(&self.called)),
+        )
+    }
+}
@@ -3,12 +3,20 @@ use pulldown_cmark::{html, Parser};
 use syntect::highlighting::ThemeSet;
 use syntect::html::highlighted_html_for_string;
 use syntect::parsing::SyntaxSet;
+use serde::Serialize;
 use tera::{Context, Tera};
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 use crate::parser::Documentation;
 use crate::templates::{DOC_TEMPLATE, INDEX_TEMPLATE};
 
+// Zed keywords, which are never linked even if a function shares the name
+const KEYWORDS: &[&str] = &[
+    "if", "else", "while", "do", "for", "array", "fn", "return", "break", "continue", "from",
+    "asm", "true", "false",
+];
+
 pub const STYLE_CSS: &str = r#"/* Zed Documentation Style */
 :root {
     --bg-color: #1a1a1a;
@@ -323,6 +331,13 @@ section {
     transform: translateY(-2px);
 }
 
+.file-summary {
+    flex: 1;
+    margin: 0 1rem;
+    color: #888;
+    font-size: 0.9rem;
+}
+
 .file-type {
     font-size: 0.8rem;
     padding: 0.2rem 0.6rem;
@@ -422,6 +437,65 @@ footer {
     }
 }"#;
 
+/// Per-module information shown on the index page
+#[derive(Debug, Serialize)]
+pub struct ModuleSummary {
+    pub name: String,
+    pub page: String,
+    pub summary: Option<String>,
+    pub function_count: usize,
+    pub functions: Vec<FunctionSummary>,
+}
+
+/// A function's entry in the search index
+#[derive(Debug, Serialize)]
+pub struct FunctionSummary {
+    pub name: String,
+    pub summary: Option<String>,
+}
+
+/// Search index entries for a module's functions, each summarized by the
+/// first line of its documentation
+fn summarize_functions(doc: &Documentation) -> Vec<FunctionSummary> {
+    doc.functions
+        .iter()
+        .map(|f| FunctionSummary {
+            name: f.name.clone(),
+            summary: f.doc_comments
+                .first()
+                .and_then(|comment| comment.lines().next())
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty()),
+        })
+        .collect()
+}
+
+// Serializes the search index for embedding in a `<script>`, where a `</`
+// inside a summary would otherwise end the script early
+fn search_index<T: Serialize>(entries: &T) -> Result<String> {
+    Ok(serde_json::to_string(entries)?.replace("</", "<\\/"))
+}
+
+/// Summarizes a module by the first line of its module documentation and
+/// its number of public functions. `page` is the module's HTML file name.
+pub fn summarize_module(name: &str, page: &str, doc: &Documentation) -> ModuleSummary {
+    // Module documentation is any doc comment not attached to a function
+    let summary = doc.module_docs
+        .iter()
+        .find(|module_doc| !doc.functions.iter().any(|f| f.doc_comments.contains(module_doc)))
+        .and_then(|module_doc| module_doc.lines().next())
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty());
+
+    ModuleSummary {
+        name: name.to_string(),
+        page: page.to_string(),
+        summary,
+        function_count: doc.functions.iter().filter(|f| f.is_public).count(),
+        functions: summarize_functions(doc),
+    }
+}
+
 pub fn generate_html(doc: &Documentation, title: Option<&str>) -> Result<String> {
     let mut tera = Tera::default();
     tera.add_raw_template("doc", DOC_TEMPLATE)?;
@@ -437,14 +511,22 @@ pub fn generate_html(doc: &Documentation, title: Option<&str>) -> Result<String>
 
     let theme = &ts.themes["base16-ocean.dark"];
 
+    // Names that link to their function's section wherever they're mentioned
+    let linkable: HashSet<&str> = doc.functions
+        .iter()
+        .map(|f| f.name.as_str())
+        .filter(|name| !KEYWORDS.contains(name))
+        .collect();
+
     let mut context = Context::new();
     context.insert("title", &title.unwrap_or("Zed Documentation"));
     context.insert("doc", &doc);
+    context.insert("search_index", &search_index(&summarize_functions(doc))?);
 
     // Convert module documentation comments to HTML
     let module_docs_html: Vec<String> = doc.module_docs
         .iter()
-        .map(|doc| markdown_to_html(doc))
+        .map(|doc| link_functions(&markdown_to_html(doc), &linkable, ""))
         .collect();
     context.insert("module_docs_html", &module_docs_html);
 
@@ -457,11 +539,11 @@ pub fn generate_html(doc: &Documentation, title: Option<&str>) -> Result<String>
             // Convert multiple documentation comments to HTML
             let doc_html: Vec<String> = f.doc_comments
                 .iter()
-                .map(|doc| markdown_to_html(doc))
+                .map(|doc| link_functions(&markdown_to_html(doc), &linkable, &f.name))
                 .collect();
             func["doc_html"] = serde_json::Value::Array(
                 doc_html.into_iter()
-                    .map(|html| serde_json::Value::String(html))
+                    .map(serde_json::Value::String)
                     .collect()
             );
 
@@ -470,10 +552,11 @@ pub fn generate_html(doc: &Documentation, title: Option<&str>) -> Result<String>
                 let highlighted = highlighted_html_for_string(
                     &f.source,
                     &ss,
-                    &syntax,
+                    syntax,
                     theme
                 ).unwrap_or_else(|_| html_escape(&f.source));
-                func["source_html"] = serde_json::Value::String(highlighted);
+                func["source_html"] =
+                    serde_json::Value::String(link_functions(&highlighted, &linkable, &f.name));
             }
             func
         })
@@ -483,7 +566,11 @@ pub fn generate_html(doc: &Documentation, title: Option<&str>) -> Result<String>
     Ok(tera.render("doc", &context)?)
 }
 
-pub fn generate_index(output_dir: &Path, title: Option<&str>) -> Result<String> {
+pub fn generate_index(
+    output_dir: &Path,
+    title: Option<&str>,
+    modules: &mut [ModuleSummary],
+) -> Result<String> {
     let mut tera = Tera::default();
     tera.add_raw_template("index", INDEX_TEMPLATE)?;
 
@@ -495,24 +582,9 @@ pub fn generate_index(output_dir: &Path, title: Option<&str>) -> Result<String>
     let mut context = Context::new();
     context.insert("title", &title.unwrap_or("Zed Documentation"));
 
-    // Collect all generated HTML files
-    let mut files = Vec::new();
-    for entry in fs::read_dir(output_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.extension().map_or(false, |ext| ext == "html") {
-            if let Some(name) = path.file_stem()
-                .and_then(|s| s.to_str())
-                .map(|s| s.to_string())
-            {
-                if name != "index" {
-                    files.push(name);
-                }
-            }
-        }
-    }
-    files.sort();
-    context.insert("files", &files);
+    modules.sort_by(|a, b| a.name.cmp(&b.name));
+    context.insert("modules", &modules);
+    context.insert("search_index", &search_index(&modules)?);
 
     let html = tera.render("index", &context)?;
     fs::write(output_dir.join("index.html"), &html)?;
@@ -520,6 +592,21 @@ pub fn generate_index(output_dir: &Path, title: Option<&str>) -> Result<String>
     Ok(html)
 }
 
+/// Serializes a module's documentation as it was parsed, for tools that
+/// render or index it themselves
+pub fn generate_json(doc: &Documentation) -> Result<String> {
+    Ok(serde_json::to_string_pretty(doc)?)
+}
+
+/// Writes `index.json`, listing each module with its JSON file and summary
+pub fn generate_json_index(output_dir: &Path, modules: &mut [ModuleSummary]) -> Result<String> {
+    modules.sort_by(|a, b| a.name.cmp(&b.name));
+    let json = serde_json::to_string_pretty(&modules)?;
+    fs::write(output_dir.join("index.json"), &json)?;
+
+    Ok(json)
+}
+
 fn markdown_to_html(markdown: &str) -> String {
     let parser = Parser::new(markdown);
     let mut html_output = String::new();
@@ -527,6 +614,54 @@ fn markdown_to_html(markdown: &str) -> String {
     html_output
 }
 
+/// Wraps each mention of a function from `names` in the text of `html` in a
+/// link to its section. Tags, entities, text already inside a link, a
+/// function's mentions of itself and `@` directives are left as they are.
+fn link_functions(html: &str, names: &HashSet<&str>, current: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut link_depth = 0usize;
+    let mut previous = ' ';
+    let mut rest = html;
+
+    while let Some(ch) = rest.chars().next() {
+        let token_len = match ch {
+            '<' => {
+                let len = rest.find('>').map_or(rest.len(), |end| end + 1);
+                let tag = &rest[..len];
+                if tag.starts_with("<a ") || tag == "<a>" {
+                    link_depth += 1;
+                } else if tag.starts_with("</a") {
+                    link_depth = link_depth.saturating_sub(1);
+                }
+                output.push_str(tag);
+                rest = &rest[len..];
+                continue;
+            }
+            '&' => {
+                let name_len = rest[1..]
+                    .find(|ch: char| !ch.is_alphanumeric() && ch != '#')
+                    .unwrap_or(rest.len() - 1);
+                if rest[1 + name_len..].starts_with(';') { name_len + 2 } else { 1 }
+            }
+            ch if ch.is_alphanumeric() || ch == '_' => rest
+                .find(|ch: char| !ch.is_alphanumeric() && ch != '_')
+                .unwrap_or(rest.len()),
+            ch => ch.len_utf8(),
+        };
+
+        let token = &rest[..token_len];
+        if link_depth == 0 && previous != '@' && token != current && names.contains(token) {
+            output.push_str(&format!("<a href=\"#fn-{}\">{}</a>", token, token));
+        } else {
+            output.push_str(token);
+        }
+        previous = token.chars().last().unwrap_or(previous);
+        rest = &rest[token_len..];
+    }
+
+    output
+}
+
 fn html_escape(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -534,3 +669,52 @@ fn html_escape(s: &str) -> String {
         .replace('"', "&quot;")
         .replace('\'', "&#39;")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, Documentation};
+
+    const SOURCE: &str = r#"/** Helpers for "greeting" people. */
+@include <std/io.zed>
+@include "local/util.zed"
+
+/**
+ * Prints a greeting.
+ * Uses `name` verbatim.
+ */
+fn greet(name, times) {
+    print("hello \"" + name + "\"\n");
+}
+
+fn _helper(x) {
+    return x;
+}
+"#;
+
+    #[test]
+    fn json_round_trips_to_the_same_documentation() {
+        let doc = parser::parse_source(SOURCE, true).unwrap();
+        let json = generate_json(&doc).unwrap();
+        let parsed: Documentation = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.module_docs, doc.module_docs);
+        assert_eq!(parsed.includes.len(), doc.includes.len());
+        for (parsed, original) in parsed.includes.iter().zip(&doc.includes) {
+            assert_eq!(parsed.path, original.path);
+            assert_eq!(parsed.is_std, original.is_std);
+        }
+        assert_eq!(parsed.functions.len(), doc.functions.len());
+        for (parsed, original) in parsed.functions.iter().zip(&doc.functions) {
+            assert_eq!(parsed.name, original.name);
+            assert_eq!(parsed.params, original.params);
+            assert_eq!(parsed.doc_comments, original.doc_comments);
+            assert_eq!(parsed.is_public, original.is_public);
+            assert_eq!(parsed.source, original.source);
+        }
+
+        assert_eq!(doc.includes.len(), 2);
+        assert_eq!(doc.functions.len(), 2);
+        assert_eq!(doc.functions[0].params, ["name", "times"]);
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 use walkdir::WalkDir;
@@ -25,6 +26,26 @@ struct Cli {
     /// Include private functions in documentation
     #[arg(long)]
     private: bool,
+
+    /// Output format: HTML pages, or one JSON file per module for editors
+    /// and other tools
+    #[arg(long, value_enum, default_value_t = Format::Html)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Html,
+    Json,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Html => "html",
+            Format::Json => "json",
+        }
+    }
 }
 
 fn main() -> Result<()> {
@@ -37,55 +58,79 @@ fn main() -> Result<()> {
         .context("Failed to create output directory")?;
 
     // Process input
-    if input_path.is_file() {
-        process_file(input_path, output_path, &cli)?;
+    let mut modules = if input_path.is_file() {
+        vec![process_file(input_path, output_path, &cli, &mut HashSet::new())?]
     } else if input_path.is_dir() {
-        process_directory(input_path, output_path, &cli)?;
+        process_directory(input_path, output_path, &cli)?
     } else {
         anyhow::bail!("Input path does not exist: {}", cli.input);
-    }
+    };
 
     // Always generate index
-    generator::generate_index(output_path, cli.title.as_deref())?;
+    match cli.format {
+        Format::Html => generator::generate_index(output_path, cli.title.as_deref(), &mut modules)?,
+        Format::Json => generator::generate_json_index(output_path, &mut modules)?,
+    };
 
     println!("Documentation generated successfully in {}", cli.output);
     Ok(())
 }
 
-fn process_file(input: &Path, output_dir: &Path, cli: &Cli) -> Result<()> {
+/// Picks the file name for a module's page. `index` belongs to the
+/// generated index, and modules with the same name in different directories
+/// would overwrite each other, so clashing names get a `_` suffix.
+fn page_name(module_name: &str, taken: &mut HashSet<String>, format: Format) -> String {
+    let mut stem = module_name.to_string();
+    while stem == "index" || !taken.insert(stem.clone()) {
+        stem.push('_');
+    }
+    format!("{}.{}", stem, format.extension())
+}
+
+fn process_file(
+    input: &Path,
+    output_dir: &Path,
+    cli: &Cli,
+    taken_pages: &mut HashSet<String>,
+) -> Result<generator::ModuleSummary> {
     println!("Processing file: {}", input.display());
 
     let source = fs::read_to_string(input)
         .context("Failed to read input file")?;
 
     let doc = parser::parse_source(&source, cli.private)?;
-    let html = generator::generate_html(&doc, cli.title.as_deref())?;
-
-    let output_file = output_dir.join(
-        input.file_name()
-            .unwrap()
-            .to_string_lossy()
-            .replace(".zed", ".html")
-    );
-
-    fs::write(&output_file, html)
+    let page_content = match cli.format {
+        Format::Html => generator::generate_html(&doc, cli.title.as_deref())?,
+        Format::Json => generator::generate_json(&doc)?,
+    };
+
+    let module_name = input.file_stem()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+    let page = page_name(&module_name, taken_pages, cli.format);
+    let output_file = output_dir.join(&page);
+
+    fs::write(&output_file, page_content)
         .context("Failed to write output file")?;
 
-    Ok(())
+    Ok(generator::summarize_module(&module_name, &page, &doc))
 }
 
-fn process_directory(input_dir: &Path, output_dir: &Path, cli: &Cli) -> Result<()> {
+fn process_directory(input_dir: &Path, output_dir: &Path, cli: &Cli) -> Result<Vec<generator::ModuleSummary>> {
     let mut files = Vec::new();
 
-    // Collect all .zed files
-    for entry in WalkDir::new(input_dir) {
+    // Collect all .zed files, in a stable order so page names don't change
+    for entry in WalkDir::new(input_dir).sort_by_file_name() {
         let entry = entry?;
-        if entry.path().extension().map_or(false, |ext| ext == "zed") {
+        if entry.path().extension().is_some_and(|ext| ext == "zed") {
             files.push(entry.path().to_owned());
         }
     }
 
     // Process each file
+    let mut modules = Vec::new();
+    let mut taken_pages = HashSet::new();
     for file in files {
         let relative_path = file.strip_prefix(input_dir)?;
         let output_path = output_dir.join(relative_path);
@@ -95,8 +140,8 @@ fn process_directory(input_dir: &Path, output_dir: &Path, cli: &Cli) -> Result<(
             fs::create_dir_all(parent)?;
         }
 
-        process_file(&file, output_dir, cli)?;
+        modules.push(process_file(&file, output_dir, cli, &mut taken_pages)?);
     }
 
-    Ok(())
+    Ok(modules)
 }
@@ -1,15 +1,16 @@
 use anyhow::Result;
 use regex::Regex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Documentation {
     pub functions: Vec<Function>,
     pub includes: Vec<Include>,
     pub module_docs: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Function {
     pub name: String,
     pub params: Vec<String>,
@@ -18,7 +19,7 @@ pub struct Function {
     pub source: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Include {
     pub path: String,
     pub is_std: bool,
@@ -32,10 +33,11 @@ pub fn parse_source(source: &str, include_private: bool) -> Result<Documentation
     };
 
     // Extract all documentation comments
-    let doc_comment_re = Regex::new(r"(/\*\*(?:[^*]|\*[^/])*\*/)")?;
-    doc.module_docs = doc_comment_re
-        .captures_iter(source)
-        .map(|cap| clean_doc_comment(&cap[1]))
+    let regions = scan_regions(source);
+    doc.module_docs = regions
+        .iter()
+        .filter(|region| region.is_doc_comment)
+        .map(|region| clean_doc_comment(&source[region.start..region.end]))
         .collect();
 
     // Parse includes
@@ -46,57 +48,144 @@ pub fn parse_source(source: &str, include_private: bool) -> Result<Documentation
         doc.includes.push(Include { path, is_std });
     }
 
-    // Parse functions with all preceding documentation comments
-    let docs_pattern = Regex::new(r"(?s)(?P<docs>(/\*\*(?:[^*]|\*[^/])*\*/\s*)*)")?;
-    let function_pattern = Regex::new(r"fn\s+(?P<name>[a-zA-Z_][a-zA-Z0-9_]*)\s*\((?P<params>[^)]*)\)")?;
+    // Parse each function once, in source order, together with the
+    // documentation comments immediately preceding it
+    let function_pattern = Regex::new(r"\bfn\s+(?P<name>[a-zA-Z_][a-zA-Z0-9_]*)\s*\((?P<params>[^)]*)\)")?;
+    let mut seen = HashSet::new();
 
-    for cap in docs_pattern.captures_iter(source) {
-        let start = cap.get(0).unwrap().end();
-        if let Some(func_cap) = function_pattern.captures(&source[start..]) {
-            let name = func_cap["name"].to_string();
-            let params = parse_params(&func_cap["params"]);
+    for func_cap in function_pattern.captures_iter(source) {
+        let signature = func_cap.get(0).unwrap();
+        let name = func_cap["name"].to_string();
 
-            // Collect all documentation comments preceding the function
-            let doc_comments = doc_comment_re
-                .captures_iter(&cap["docs"])
-                .map(|doc_cap| clean_doc_comment(&doc_cap[1]))
-                .collect::<Vec<_>>();
+        // Ignore `fn` appearing inside strings and comments
+        if regions
+            .iter()
+            .any(|region| region.start <= signature.start() && signature.start() < region.end)
+        {
+            continue;
+        }
+
+        // Extract function source, skipping predeclarations
+        let source_text = match extract_function_source(source, signature.end()) {
+            Some(src) => src,
+            None => continue,
+        };
+
+        // A name can only be defined once; ignore any later duplicates
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+
+        // Determine if function is public (no _ prefix)
+        let is_public = !name.starts_with('_');
+
+        // Skip private functions unless explicitly included
+        if !is_public && !include_private {
+            continue;
+        }
+
+        // Collect all documentation comments preceding the function
+        let doc_comments = preceding_doc_comments(source, &regions, signature.start());
+
+        doc.functions.push(Function {
+            name,
+            params: parse_params(&func_cap["params"]),
+            doc_comments,
+            is_public,
+            source: source_text,
+        });
+    }
+
+    Ok(doc)
+}
+
+// A string or character literal, or a comment, in the source
+struct Region {
+    start: usize,
+    end: usize,
+    is_doc_comment: bool,
+}
 
-            // Determine if function is public (no _ prefix)
-            let is_public = !name.starts_with('_');
+/// Finds string and character literals and comments in source order, so that delimiters
+/// inside them, like a `*/` in a string, aren't taken for comment boundaries.
+fn scan_regions(source: &str) -> Vec<Region> {
+    let bytes = source.as_bytes();
+    let mut regions = Vec::new();
+    let mut i = 0;
 
-            // Skip private functions unless explicitly included
-            if !is_public && !include_private {
+    while i < bytes.len() {
+        let start = i;
+        let is_doc_comment = match bytes[i] {
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i = (i + 1).min(bytes.len());
+                false
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                false
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                // `/**/` is an empty comment, not a doc comment
+                let is_doc_comment =
+                    bytes.get(i + 2) == Some(&b'*') && bytes.get(i + 3) != Some(&b'/');
+                i = match source[i + 2..].find("*/") {
+                    Some(offset) => i + 2 + offset + 2,
+                    None => bytes.len(),
+                };
+                is_doc_comment
+            }
+            _ => {
+                i += 1;
                 continue;
             }
+        };
+
+        regions.push(Region {
+            start,
+            end: i,
+            is_doc_comment,
+        });
+    }
+
+    regions
+}
+
+/// Returns the doc comments directly before `position`, separated from it
+/// and from each other only by whitespace.
+fn preceding_doc_comments(source: &str, regions: &[Region], position: usize) -> Vec<String> {
+    let mut docs = Vec::new();
+    let mut end = source[..position].trim_end().len();
 
-            // Extract function source
-            let source = if let Some(src) = extract_function_source(source, &name)? {
-                src
-            } else {
-                continue; // Skip if we can't find the source
-            };
-
-            doc.functions.push(Function {
-                name,
-                params,
-                doc_comments,
-                is_public,
-                source,
-            });
+    for region in regions.iter().rev().filter(|region| region.end <= position) {
+        if !region.is_doc_comment || region.end != end {
+            break;
         }
+        docs.push(clean_doc_comment(&source[region.start..region.end]));
+        end = source[..region.start].trim_end().len();
     }
 
-    Ok(doc)
+    docs.reverse();
+    docs
 }
 
 fn clean_doc_comment(comment: &str) -> String {
     comment
         .trim_start_matches("/**")
         .trim_end_matches("*/")
+        .trim_end_matches('*')
         .lines()
         .map(|line| {
-            line.trim_start_matches('*')
+            line.trim_start()
+                .trim_start_matches('*')
                 .trim_start()
                 .to_string()
         })
@@ -114,30 +203,57 @@ fn parse_params(params: &str) -> Vec<String> {
         .collect()
 }
 
-fn extract_function_source(source: &str, function_name: &str) -> Result<Option<String>> {
-    let pattern = format!(
-        r"fn\s+{}\s*\([^)]*\)\s*({{\s*(?:[^{{}}]|(?R))*\s*}})",
-        regex::escape(function_name)
-    );
-
-    // First try with recursive pattern
-    if let Ok(re) = Regex::new(&pattern) {
-        if let Some(cap) = re.captures(source) {
-            return Ok(Some(cap[1].trim().to_string()));
-        }
+/// Extracts the brace-delimited body that starts after a function signature.
+/// Returns `None` for predeclarations, which have no body.
+fn extract_function_source(source: &str, signature_end: usize) -> Option<String> {
+    let rest = &source[signature_end..];
+    let body = rest.trim_start();
+    if !body.starts_with('{') {
+        return None;
     }
 
-    // Fallback to simpler pattern that matches until the next function declaration
-    let fallback_pattern = format!(
-        r"fn\s+{}\s*\([^)]*\)\s*({{[\s\S]*?(?=\bfn\b|$)}})",
-        regex::escape(function_name)
-    );
+    let bytes = body.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
 
-    if let Ok(re) = Regex::new(&fallback_pattern) {
-        if let Some(cap) = re.captures(source) {
-            return Ok(Some(cap[1].trim().to_string()));
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(body[..=i].trim().to_string());
+                }
+            }
+            // Skip string and character literals so braces inside them aren't counted
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            // Skip line comments
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            // Skip block comments
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i + 1 < bytes.len() && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
+                    i += 1;
+                }
+                i += 1;
+            }
+            _ => {}
         }
+        i += 1;
     }
 
-    Ok(None)
+    // Unbalanced braces: fall back to the rest of the file
+    Some(body.trim().to_string())
 }
@@ -68,11 +68,15 @@ pub const DOC_TEMPLATE: &str = r###"<!DOCTYPE html>
         <section id="functions">
             <h2>Functions</h2>
 
+            <div class="search-box">
+                <input type="text" id="search" placeholder="Search functions..." oninput="filterFunctions()">
+            </div>
+
             <div class="function-list">
                 <h3>Quick Jump</h3>
                 <ul>
                 {% for function in functions %}
-                    <li>
+                    <li data-function="{{ function.name }}">
                         <a href="#fn-{{ function.name }}">{{ function.name }}</a>
                         <span class="visibility-badge {% if function.is_public %}public{% else %}private{% endif %}">
                             {% if function.is_public %}public{% else %}private{% endif %}
@@ -133,6 +137,23 @@ pub const DOC_TEMPLATE: &str = r###"<!DOCTYPE html>
     </footer>
 
     <script>
+        const searchIndex = {{ search_index | safe }};
+
+        function matches(entry, filter) {
+            return entry.name.toLowerCase().includes(filter)
+                || (entry.summary || '').toLowerCase().includes(filter);
+        }
+
+        function filterFunctions() {
+            const filter = document.getElementById('search').value.toLowerCase();
+
+            for (const entry of searchIndex) {
+                const display = matches(entry, filter) ? '' : 'none';
+                document.getElementById('fn-' + entry.name).style.display = display;
+                document.querySelector('[data-function="' + entry.name + '"]').style.display = display;
+            }
+        }
+
         function toggleSource(button) {
             const source = button.parentElement.nextElementSibling;
             if (source.style.display === 'none') {
@@ -165,15 +186,16 @@ pub const INDEX_TEMPLATE: &str = r###"<!DOCTYPE html>
     <div class="container">
         <div class="documentation-index">
             <h2>Documentation Files</h2>
-            {% if files %}
+            {% if modules %}
             <div class="search-box">
-                <input type="text" id="search" placeholder="Search files..." onkeyup="filterFiles()">
+                <input type="text" id="search" placeholder="Search files and functions..." oninput="filterFiles()">
             </div>
             <ul class="file-list" id="fileList">
-            {% for file in files %}
-                <li class="file-item">
-                    <a href="{{ file }}.html">{{ file }}</a>
-                    <span class="file-type">Module</span>
+            {% for module in modules %}
+                <li class="file-item" data-page="{{ module.page }}">
+                    <a href="{{ module.page }}">{{ module.name }}</a>
+                    <span class="file-summary">{% if module.summary %}{{ module.summary }}{% endif %}</span>
+                    <span class="file-type">{{ module.function_count }} function{% if module.function_count != 1 %}s{% endif %}</span>
                 </li>
             {% endfor %}
             </ul>
@@ -186,7 +208,7 @@ pub const INDEX_TEMPLATE: &str = r###"<!DOCTYPE html>
             <h3>Navigation</h3>
             <ul>
                 <li>Click on any file to view its documentation</li>
-                <li>Use the search box to filter files</li>
+                <li>Use the search box to filter files by name, summary or the functions they define</li>
                 <li>Each file contains:
                     <ul>
                         <li>Module documentation</li>
@@ -206,19 +228,21 @@ pub const INDEX_TEMPLATE: &str = r###"<!DOCTYPE html>
     </footer>
 
     <script>
+        const searchIndex = {{ search_index | safe }};
+
+        function matches(entry, filter) {
+            return entry.name.toLowerCase().includes(filter)
+                || (entry.summary || '').toLowerCase().includes(filter);
+        }
+
         function filterFiles() {
-            const input = document.getElementById('search');
-            const filter = input.value.toLowerCase();
-            const list = document.getElementById('fileList');
-            const items = list.getElementsByTagName('li');
-
-            for (let item of items) {
-                const text = item.textContent || item.innerText;
-                if (text.toLowerCase().indexOf(filter) > -1) {
-                    item.style.display = '';
-                } else {
-                    item.style.display = 'none';
-                }
+            const filter = document.getElementById('search').value.toLowerCase();
+
+            for (const module of searchIndex) {
+                const found = matches(module, filter)
+                    || module.functions.some(entry => matches(entry, filter));
+                const item = document.querySelector('[data-page="' + module.page + '"]');
+                item.style.display = found ? '' : 'none';
             }
         }
     </script>
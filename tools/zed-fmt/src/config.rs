@@ -0,0 +1,37 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+const CONFIG_FILE: &str = ".zedfmt";
+
+/// Settings read from a `.zedfmt` file. Anything left out keeps its default,
+/// and command-line flags override what's here.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    pub indent_spaces: Option<usize>,
+    pub tabs: Option<bool>,
+    pub max_width: Option<usize>,
+    pub align_assignments: Option<bool>,
+    pub sort_includes: Option<bool>,
+}
+
+impl FileConfig {
+    /// Reads the `.zedfmt` nearest to `path`, looking in the directory that
+    /// holds it and then each directory above. With no `.zedfmt` anywhere,
+    /// every setting is left to its default.
+    pub fn discover(path: &Path) -> Result<Self> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        for dir in path.ancestors().skip(1) {
+            let candidate = dir.join(CONFIG_FILE);
+            if candidate.is_file() {
+                let content = fs::read_to_string(&candidate)
+                    .with_context(|| format!("Failed to read {}", candidate.display()))?;
+                return toml::from_str(&content)
+                    .with_context(|| format!("Invalid settings in {}", candidate.display()));
+            }
+        }
+        Ok(Self::default())
+    }
+}
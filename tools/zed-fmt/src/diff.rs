@@ -0,0 +1,179 @@
+use colored::*;
+
+// Unchanged lines shown around each change, as in `diff -u`
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit<'a> {
+    Keep(&'a str),
+    Remove(&'a str),
+    Add(&'a str),
+}
+
+/// Renders a colored unified diff from `original` to `formatted`, or an
+/// empty string when they're the same.
+pub fn unified_diff(path: &str, original: &str, formatted: &str) -> String {
+    let old: Vec<&str> = original.lines().collect();
+    let new: Vec<&str> = formatted.lines().collect();
+    let edits = diff_lines(&old, &new);
+
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Keep(_)))
+        .map(|(index, _)| index)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Line numbers in each file where every edit starts
+    let mut old_line = Vec::with_capacity(edits.len());
+    let mut new_line = Vec::with_capacity(edits.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for edit in &edits {
+        old_line.push(old_pos);
+        new_line.push(new_pos);
+        match edit {
+            Edit::Keep(_) => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Edit::Remove(_) => old_pos += 1,
+            Edit::Add(_) => new_pos += 1,
+        }
+    }
+
+    let mut output = format!("{}\n{}\n", format!("--- {}", path).red(), format!("+++ {}", path).green());
+
+    // Changes close enough that their context would overlap share a hunk
+    let mut next = 0;
+    while next < changes.len() {
+        let mut last = next;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * CONTEXT_LINES + 1 {
+            last += 1;
+        }
+        let start = changes[next].saturating_sub(CONTEXT_LINES);
+        let end = (changes[last] + 1 + CONTEXT_LINES).min(edits.len());
+        next = last + 1;
+
+        let hunk = &edits[start..end];
+        let old_count = hunk.iter().filter(|edit| !matches!(edit, Edit::Add(_))).count();
+        let new_count = hunk.iter().filter(|edit| !matches!(edit, Edit::Remove(_))).count();
+        output.push_str(&format!(
+            "{}\n",
+            format!(
+                "@@ -{} +{} @@",
+                hunk_range(old_line[start], old_count),
+                hunk_range(new_line[start], new_count)
+            )
+            .cyan()
+        ));
+
+        for edit in hunk {
+            let line = match edit {
+                Edit::Keep(line) => format!(" {}", line).normal(),
+                Edit::Remove(line) => format!("-{}", line).red(),
+                Edit::Add(line) => format!("+{}", line).green(),
+            };
+            output.push_str(&format!("{}\n", line));
+        }
+    }
+
+    output
+}
+
+// `diff -u` numbers lines from 1, except that an empty range names the line
+// before it
+fn hunk_range(start: usize, count: usize) -> String {
+    if count == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, count)
+    }
+}
+
+/// Lines of `old` and `new` as keeps, removals and additions, from their
+/// longest common subsequence. The common prefix and suffix are matched
+/// first, which keeps the table small since formatting changes are usually
+/// spread thinly through a file.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] is the length of the longest common subsequence of
+    // old_middle[i..] and new_middle[j..]
+    let mut lcs = vec![vec![0usize; new_middle.len() + 1]; old_middle.len() + 1];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lcs[i][j] = if old_middle[i] == new_middle[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits: Vec<Edit> = old[..prefix].iter().map(|line| Edit::Keep(line)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() && j < new_middle.len() {
+        if old_middle[i] == new_middle[j] {
+            edits.push(Edit::Keep(old_middle[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            edits.push(Edit::Remove(old_middle[i]));
+            i += 1;
+        } else {
+            edits.push(Edit::Add(new_middle[j]));
+            j += 1;
+        }
+    }
+    edits.extend(old_middle[i..].iter().map(|line| Edit::Remove(line)));
+    edits.extend(new_middle[j..].iter().map(|line| Edit::Add(line)));
+    edits.extend(old[old.len() - suffix..].iter().map(|line| Edit::Keep(line)));
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain_diff(original: &str, formatted: &str) -> String {
+        colored::control::set_override(false);
+        unified_diff("f.zed", original, formatted)
+    }
+
+    #[test]
+    fn shows_a_changed_line_with_its_context() {
+        assert_eq!(
+            plain_diff("a\nb\nc\n", "a\nB\nc\n"),
+            "--- f.zed\n+++ f.zed\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+    }
+
+    #[test]
+    fn puts_distant_changes_in_separate_hunks() {
+        let original: Vec<String> = (0..20).map(|i| format!("l{}", i)).collect();
+        let mut formatted = original.clone();
+        formatted[1] = "L1".to_string();
+        formatted[18] = "L18".to_string();
+
+        let diff = plain_diff(&original.join("\n"), &formatted.join("\n"));
+        assert_eq!(diff.matches("@@ -").count(), 2);
+        assert!(diff.contains("@@ -1,5 +1,5 @@\n l0\n-l1\n+L1\n l2\n l3\n l4\n"));
+        assert!(diff.contains("@@ -16,5 +16,5 @@\n l15\n l16\n l17\n-l18\n+L18\n l19\n"));
+    }
+
+    #[test]
+    fn identical_input_has_no_diff() {
+        assert_eq!(plain_diff("a\nb\n", "a\nb\n"), "");
+    }
+}
@@ -1,10 +1,26 @@
 use anyhow::Result;
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct Config {
+    pub indent_style: IndentStyle,
     pub indent_spaces: usize,
+    // Calls longer than this are wrapped after their commas
     pub max_width: usize,
+    // Only lines in this 1-based inclusive range are reformatted; the rest
+    // are kept byte for byte
+    pub line_range: Option<(usize, usize)>,
+    // Pad consecutive assignments at the same indentation so their `=`
+    // signs share a column
+    pub align_assignments: bool,
+    // Sort each block of `@include` lines, grouping `<std/...>` includes
+    // ahead of local `"..."` ones
+    pub sort_includes: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndentStyle {
+    Spaces,
+    Tabs,
 }
 
 pub fn format_source(source: &str, config: &Config) -> Result<String> {
@@ -12,11 +28,21 @@ pub fn format_source(source: &str, config: &Config) -> Result<String> {
     formatter.format(source)
 }
 
-#[allow(dead_code)]
 struct Formatter<'a> {
     config: &'a Config,
     output: String,
     indent_level: usize,
+    // Whether the previous line ended inside a string or block comment
+    in_string: bool,
+    in_block_comment: bool,
+    // Whether the line being formatted started inside one
+    line_starts_inside: bool,
+    // The indentation lining up with each `(` still open from earlier
+    // lines, innermost last; continuation lines use the innermost one
+    open_parens: Vec<String>,
+    // Whether the line being formatted is part of a call spread over
+    // several lines, either by wrapping it here or already in the source
+    wrapped: bool,
 }
 
 impl<'a> Formatter<'a> {
@@ -25,20 +51,70 @@ impl<'a> Formatter<'a> {
             config,
             output: String::new(),
             indent_level: 0,
+            in_string: false,
+            in_block_comment: false,
+            line_starts_inside: false,
+            open_parens: Vec::new(),
+            wrapped: false,
         }
     }
 
     fn format(&mut self, source: &str) -> Result<String> {
+        let original_lines: Vec<&str> = source.split_inclusive('\n').collect();
+
+        // For each output line, whether the layout passes may rewrite it: it
+        // was reformatted and lies wholly outside strings and block comments
+        let mut rewritable = Vec::new();
+
         // Normalize line endings
         let source = source.replace("\r\n", "\n");
 
         // Process each line
-        for line in source.lines() {
-            self.format_line(line)?;
+        let mut last_in_range = true;
+        for (index, line) in source.lines().enumerate() {
+            // A shebang on the first line is passed through untouched
+            if index == 0 && line.starts_with("#!") {
+                self.output.push_str(line);
+                self.output.push('\n');
+                rewritable.push(false);
+                continue;
+            }
+
+            let formatted_start = self.output.len();
+            let starts_inside = self.in_string || self.in_block_comment;
+            self.line_starts_inside = starts_inside;
+            self.wrapped = false;
+            for statement in self.split_statements(line) {
+                self.format_line(statement)?;
+            }
+            let ends_inside = self.in_string || self.in_block_comment;
+
+            // Lines outside the range are still formatted so the indentation
+            // of later lines is right, but their original text is kept
+            last_in_range = match self.config.line_range {
+                Some((start, end)) => (start..=end).contains(&(index + 1)),
+                None => true,
+            };
+            if !last_in_range {
+                self.output.truncate(formatted_start);
+                self.output.push_str(original_lines[index]);
+            }
+            let lines_written = self.output[formatted_start..].matches('\n').count().max(1);
+            let can_rewrite = last_in_range && !starts_inside && !ends_inside && !self.wrapped;
+            rewritable.extend(std::iter::repeat_n(can_rewrite, lines_written));
+        }
+
+        // Aligning keeps one output line per line, so it runs first while
+        // `rewritable` still lines up with the output
+        if self.config.align_assignments {
+            self.align_assignments(&rewritable);
+        }
+        if self.config.sort_includes {
+            self.sort_includes(&rewritable);
         }
 
         // Ensure final newline
-        if !self.output.ends_with('\n') {
+        if last_in_range && !self.output.ends_with('\n') {
             self.output.push('\n');
         }
 
@@ -57,8 +133,27 @@ impl<'a> Formatter<'a> {
             self.indent_level = self.indent_level.saturating_sub(1);
         }
 
-        // Write the line with proper indentation
-        self.write_indented(trimmed)?;
+        // Write the line with proper indentation, lining a continuation up
+        // with the open paren it continues and wrapping calls that are
+        // too long
+        match self.open_parens.last().cloned() {
+            Some(prefix) if !self.line_starts_inside => {
+                self.wrapped = true;
+                self.write_line(&prefix, trimmed);
+            }
+            _ => {
+                let indent = self.indent();
+                match self.wrap_call(&indent, trimmed) {
+                    Some(lines) => {
+                        self.wrapped = true;
+                        for (prefix, content) in lines {
+                            self.write_line(&prefix, &content);
+                        }
+                    }
+                    None => self.write_line(&indent, trimmed),
+                }
+            }
+        }
 
         // Adjust indentation for next line - handles both braces and control structures
         if trimmed.ends_with('{') ||
@@ -70,11 +165,481 @@ impl<'a> Formatter<'a> {
         Ok(())
     }
 
-    fn write_indented(&mut self, content: &str) -> Result<()> {
-        let indent = " ".repeat(self.indent_level * self.config.indent_spaces);
-        self.output.push_str(&indent);
+    // Splits a line holding several statements, like `a = 1; b = 2;`, into
+    // one piece per statement. Lines with braces are kept whole since
+    // indentation follows them, as are lines starting in a string or comment.
+    fn split_statements<'l>(&mut self, line: &'l str) -> Vec<&'l str> {
+        let starts_inside = self.in_string || self.in_block_comment;
+        let mut splits = Vec::new();
+        let mut paren_depth = 0usize;
+        let mut has_brace = false;
+
+        let mut chars = line.char_indices().peekable();
+        while let Some((pos, ch)) = chars.next() {
+            let next = chars.peek().map(|&(_, next)| next);
+
+            if self.in_block_comment {
+                if ch == '*' && next == Some('/') {
+                    self.in_block_comment = false;
+                    chars.next();
+                }
+            } else if self.in_string {
+                if ch == '\\' {
+                    chars.next();
+                } else if ch == '"' {
+                    self.in_string = false;
+                }
+            } else {
+                match ch {
+                    '"' => self.in_string = true,
+                    // Character literals never span lines, so skip them here
+                    '\'' => {
+                        while let Some((_, ch)) = chars.next() {
+                            if ch == '\\' {
+                                chars.next();
+                            } else if ch == '\'' {
+                                break;
+                            }
+                        }
+                    }
+                    '/' if next == Some('/') => break,
+                    '/' if next == Some('*') => {
+                        self.in_block_comment = true;
+                        chars.next();
+                    }
+                    '(' => paren_depth += 1,
+                    ')' => paren_depth = paren_depth.saturating_sub(1),
+                    '{' | '}' => has_brace = true,
+                    ';' if paren_depth == 0 => splits.push(pos + 1),
+                    _ => {}
+                }
+            }
+        }
+
+        if starts_inside || has_brace {
+            return vec![line];
+        }
+
+        let mut statements = Vec::new();
+        let mut start = 0;
+        for split in splits {
+            // Keep a trailing comment on the same line as its statement
+            let rest = line[split..].trim_start();
+            if rest.is_empty() || rest.starts_with("//") || rest.starts_with("/*") {
+                break;
+            }
+            statements.push(&line[start..split]);
+            start = split;
+        }
+        statements.push(&line[start..]);
+
+        statements
+    }
+
+    // Lines up runs of consecutive assignments that share an indentation,
+    // like gofmt does for struct fields. Anything else, including a blank
+    // line or a brace, ends a run.
+    fn align_assignments(&mut self, rewritable: &[bool]) {
+        let lines: Vec<&str> = self.output.split_inclusive('\n').collect();
+        let assignments: Vec<Option<(&str, &str)>> = lines
+            .iter()
+            .enumerate()
+            .map(|(index, line)| {
+                if rewritable.get(index).copied().unwrap_or(false) {
+                    split_assignment(line.trim_end_matches('\n'))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let mut output = String::with_capacity(self.output.len());
+        let mut index = 0;
+        while index < lines.len() {
+            let Some((lhs, _)) = assignments[index] else {
+                output.push_str(lines[index]);
+                index += 1;
+                continue;
+            };
+
+            let indent = indentation(lhs);
+            let run_end = (index..lines.len())
+                .find(|&next| !matches!(assignments[next], Some((lhs, _)) if indentation(lhs) == indent))
+                .unwrap_or(lines.len());
+            let width = assignments[index..run_end]
+                .iter()
+                .flatten()
+                .map(|(lhs, _)| lhs.chars().count())
+                .max()
+                .unwrap_or(0);
+            for (lhs, rhs) in assignments[index..run_end].iter().flatten() {
+                output.push_str(&format!("{:<width$} = {}\n", lhs, rhs, width = width));
+            }
+            index = run_end;
+        }
+
+        self.output = output;
+    }
+
+    // Sorts each block of consecutive `@include` lines by path, with
+    // `<std/...>` includes first, then a blank line, then local `"..."`
+    // includes. Blank lines inside a block are replaced by that grouping;
+    // any other line ends the block, so nothing moves past it.
+    fn sort_includes(&mut self, rewritable: &[bool]) {
+        let lines: Vec<&str> = self.output.split_inclusive('\n').collect();
+        let can_rewrite = |index: usize| rewritable.get(index).copied().unwrap_or(false);
+        let is_include = |index: usize| can_rewrite(index) && include_path(lines[index]).is_some();
+        let is_blank = |index: usize| can_rewrite(index) && lines[index].trim().is_empty();
+
+        let mut output = String::with_capacity(self.output.len());
+        let mut index = 0;
+        while index < lines.len() {
+            if !is_include(index) {
+                output.push_str(lines[index]);
+                index += 1;
+                continue;
+            }
+
+            // The block ends at its last include; blank lines after that
+            // are left where they are
+            let mut end = index + 1;
+            let mut next = index + 1;
+            while next < lines.len() && (is_include(next) || is_blank(next)) {
+                if is_include(next) {
+                    end = next + 1;
+                }
+                next += 1;
+            }
+
+            let (mut system, mut local): (Vec<&str>, Vec<&str>) = lines[index..end]
+                .iter()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.trim_end())
+                .partition(|line| include_path(line).is_some_and(|path| path.starts_with('<')));
+            system.sort_by_key(|line| include_path(line));
+            local.sort_by_key(|line| include_path(line));
+
+            for line in &system {
+                output.push_str(line);
+                output.push('\n');
+            }
+            if !system.is_empty() && !local.is_empty() {
+                output.push('\n');
+            }
+            for line in &local {
+                output.push_str(line);
+                output.push('\n');
+            }
+            index = end;
+        }
+
+        self.output = output;
+    }
+
+    fn indent(&self) -> String {
+        match self.config.indent_style {
+            IndentStyle::Spaces => " ".repeat(self.indent_level * self.config.indent_spaces),
+            IndentStyle::Tabs => "\t".repeat(self.indent_level),
+        }
+    }
+
+    // Splits a call longer than `max_width` after the commas between its
+    // arguments, filling each line as far as it fits. Continuation lines
+    // start just after the call's `(`. Lines inside strings or comments,
+    // asm and control-flow headers are left alone.
+    fn wrap_call(&self, indent: &str, content: &str) -> Option<Vec<(String, String)>> {
+        let width = |prefix: &str, text: &str| {
+            prefix
+                .chars()
+                .map(|ch| if ch == '\t' { self.config.indent_spaces } else { 1 })
+                .sum::<usize>()
+                + text.chars().count()
+        };
+        if self.line_starts_inside || width(indent, content) <= self.config.max_width {
+            return None;
+        }
+        if content.starts_with('}')
+            || ["if", "while", "else", "asm"].iter().any(|word| starts_with_keyword(content, word))
+        {
+            return None;
+        }
+
+        let (open, commas) = call_arguments(content)?;
+        let continuation = format!("{}{}", indent, " ".repeat(content[..=open].chars().count()));
+
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        for comma in commas {
+            pieces.push(content[start..=comma].trim());
+            start = comma + 1;
+        }
+        pieces.push(content[start..].trim());
+
+        let mut lines = Vec::new();
+        let mut prefix = indent.to_string();
+        let mut line = pieces[0].to_string();
+        for piece in &pieces[1..] {
+            if width(&prefix, &line) + 1 + piece.chars().count() <= self.config.max_width {
+                line.push(' ');
+                line.push_str(piece);
+            } else {
+                lines.push((prefix, line));
+                prefix = continuation.clone();
+                line = piece.to_string();
+            }
+        }
+        lines.push((prefix, line));
+
+        (lines.len() > 1).then_some(lines)
+    }
+
+    fn write_line(&mut self, prefix: &str, content: &str) {
+        self.output.push_str(prefix);
         self.output.push_str(content);
         self.output.push('\n');
-        Ok(())
+
+        if self.line_starts_inside {
+            return;
+        }
+        for (position, ch) in code_chars(content) {
+            match ch {
+                '(' => self.open_parens.push(format!(
+                    "{}{}",
+                    prefix,
+                    " ".repeat(content[..=position].chars().count())
+                )),
+                ')' => {
+                    self.open_parens.pop();
+                }
+                _ => {}
+            }
+        }
+        // A statement or block boundary ends any continuation, so an
+        // unbalanced paren can't misalign the rest of the file
+        if content.ends_with(';') || content.ends_with('{') || content.ends_with('}') {
+            self.open_parens.clear();
+        }
+        self.wrapped |= !self.open_parens.is_empty();
+    }
+}
+
+// The leading whitespace of a line
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+// Splits a one-line assignment statement like `x = 1;` into the text before
+// its `=`, indentation included, and the text after it. Comparisons,
+// compound assignments, calls, declarations and lines with braces aren't
+// assignments here.
+fn split_assignment(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("//") || trimmed.starts_with('@') || trimmed.starts_with("return") {
+        return None;
+    }
+
+    let bytes = line.as_bytes();
+    let mut quote = None;
+    let mut position = 0;
+    while position < bytes.len() {
+        let byte = bytes[position];
+        match quote {
+            Some(_) if byte == b'\\' => position += 1,
+            Some(open) if byte == open => quote = None,
+            Some(_) => {}
+            None => match byte {
+                b'"' | b'\'' => quote = Some(byte),
+                b'(' | b'{' | b'}' => return None,
+                b'/' if bytes.get(position + 1) == Some(&b'/') => return None,
+                b'=' => {
+                    let before = position.checked_sub(1).map(|p| bytes[p]);
+                    let after = bytes.get(position + 1).copied();
+                    let is_operator = matches!(
+                        before,
+                        Some(b'=' | b'!' | b'<' | b'>' | b'+' | b'-' | b'*' | b'/' | b'%' | b'&' | b'|' | b'^')
+                    ) || after == Some(b'=');
+                    if is_operator {
+                        return None;
+                    }
+                    let lhs = line[..position].trim_end();
+                    let rhs = line[position + 1..].trim_start();
+                    if lhs.trim().is_empty() || rhs.is_empty() {
+                        return None;
+                    }
+                    return Some((lhs, rhs));
+                }
+                _ => {}
+            },
+        }
+        position += 1;
+    }
+    None
+}
+
+// The quoted or bracketed path of an `@include` line, like `<std/io.zed>`
+fn include_path(line: &str) -> Option<&str> {
+    let path = line.trim().strip_prefix("@include")?.trim();
+    let path = path.strip_suffix(';').unwrap_or(path).trim_end();
+    (path.starts_with('<') || path.starts_with('"')).then_some(path)
+}
+
+// Whether `line` starts with the keyword `word`, rather than with a longer
+// name like `iffy` that begins with it
+fn starts_with_keyword(line: &str, word: &str) -> bool {
+    line.strip_prefix(word)
+        .is_some_and(|rest| !rest.starts_with(|ch: char| ch.is_alphanumeric() || ch == '_'))
+}
+
+// The characters of a line that are code, with their byte positions:
+// string and character literals are skipped, and a comment ends the line
+fn code_chars(line: &str) -> Vec<(usize, char)> {
+    let mut code = Vec::new();
+    let mut quote = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((position, ch)) = chars.next() {
+        match quote {
+            Some(_) if ch == '\\' => {
+                chars.next();
+            }
+            Some(open) if ch == open => quote = None,
+            Some(_) => {}
+            None => match ch {
+                '"' | '\'' => quote = Some(ch),
+                '/' if matches!(chars.peek(), Some((_, '/' | '*'))) => break,
+                _ => code.push((position, ch)),
+            },
+        }
+    }
+    code
+}
+
+// Finds the first call or parameter list on a line that holds more than one
+// argument, returning the position of its `(` and of the commas between its
+// arguments. Its `)` has to be on the same line.
+fn call_arguments(line: &str) -> Option<(usize, Vec<usize>)> {
+    let code = code_chars(line);
+    for (index, &(open, ch)) in code.iter().enumerate() {
+        let after_name = index > 0 && {
+            let previous = code[index - 1].1;
+            previous.is_alphanumeric() || previous == '_'
+        };
+        if ch != '(' || !after_name {
+            continue;
+        }
+
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        for &(position, ch) in &code[index + 1..] {
+            match ch {
+                '(' | '[' => depth += 1,
+                ')' if depth == 0 => {
+                    return if commas.is_empty() { None } else { Some((open, commas)) };
+                }
+                ')' | ']' => depth -= 1,
+                ',' if depth == 0 => commas.push(position),
+                _ => {}
+            }
+        }
+        return None;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn config() -> Config {
+        Config {
+            indent_style: IndentStyle::Spaces,
+            indent_spaces: 4,
+            max_width: 100,
+            line_range: None,
+            align_assignments: false,
+            sort_includes: false,
+        }
+    }
+
+    // Sources that exercise operators, compound assignments, CRLF line
+    // endings, multi-line asm and block comments
+    const IDEMPOTENCY_CASES: &[&str] = &[
+        "if (a <= b && c >= d || e == f) { x = 1; }\n",
+        "x += 1; y -= 2; z *= 3; w /= 4;\nlong_name = 5;\n",
+        "fn f() {\r\nx = 1;\r\n    return x;\r\n}\r\n",
+        "asm \"movq $1, %rax\n    syscall\n    ret\" : : : \"rax\";\nx = 1;\n",
+        "/* a; b\n   c = d; */ e = f;\nx = 1; /* g */ y = 2;\n",
+        "@include \"b.zed\";\n@include <std/io.zed>;\n@include \"a.zed\";\n",
+        "r = call_it(alpha_one, beta_two(x, y), gamma_three, \"s, t\", delta_four);\n",
+        "w = outer(inner(aaaa, bbbb, cccc, dddd), eeee, ffff) + h(iiii, jjjj);\n",
+    ];
+
+    // The repo's own Zed sources, from std/ and examples/
+    fn repo_sources() -> Vec<String> {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let mut sources = Vec::new();
+        for dir in ["std", "examples"] {
+            for entry in walkdir::WalkDir::new(root.join(dir)).sort_by_file_name() {
+                let entry = entry.unwrap();
+                if entry.path().extension().is_some_and(|ext| ext == "zed") {
+                    sources.push(std::fs::read_to_string(entry.path()).unwrap());
+                }
+            }
+        }
+        assert!(!sources.is_empty(), "no sources found under {}", root.display());
+        sources
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let configs = [
+            config(),
+            Config { align_assignments: true, ..config() },
+            Config { sort_includes: true, ..config() },
+            Config { max_width: 30, ..config() },
+            Config { max_width: 30, align_assignments: true, sort_includes: true, ..config() },
+        ];
+        let mut sources = repo_sources();
+        sources.extend(IDEMPOTENCY_CASES.iter().map(|case| case.to_string()));
+
+        for config in &configs {
+            for source in &sources {
+                let once = format_source(source, config).unwrap();
+                let twice = format_source(&once, config).unwrap();
+                assert_eq!(once, twice, "formatting again changed it, with {:?}:\n{}", config, source);
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_operators_inside_strings_alone() {
+        let source = "msg = \"1+1=2\";\n";
+        assert_eq!(format_source(source, &config()).unwrap(), source);
+    }
+
+    #[test]
+    fn leaves_comments_alone() {
+        let source = "// a/b/c\n";
+        assert_eq!(format_source(source, &config()).unwrap(), source);
+    }
+
+    #[test]
+    fn wraps_long_calls_after_the_open_paren() {
+        let config = Config { max_width: 40, ..config() };
+        let source = "fn f() {\ntotal = combine(first_value, second_value, third_value);\n}\n";
+        assert_eq!(
+            format_source(source, &config).unwrap(),
+            "fn f() {\n    total = combine(first_value,\n                    second_value,\n                    third_value);\n}\n"
+        );
+    }
+
+    #[test]
+    fn wraps_names_that_start_with_a_keyword() {
+        let config = Config { max_width: 30, ..config() };
+        for name in ["iffy", "asmbuf", "elsewhere", "whileCount"] {
+            let source = format!("{} = combine(first, second, third);\n", name);
+            let formatted = format_source(&source, &config).unwrap();
+            assert!(formatted.lines().count() > 1, "{} wasn't wrapped:\n{}", name, formatted);
+        }
     }
 }
@@ -5,6 +5,8 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+mod config;
+mod diff;
 mod formatter;
 
 #[derive(Parser)]
@@ -22,13 +24,54 @@ struct Cli {
     #[arg(short, long)]
     check: bool,
 
-    /// Number of spaces per indentation level
-    #[arg(short, long, default_value = "4")]
-    indent: usize,
+    /// Number of spaces per indentation level [default: 4]
+    #[arg(short, long)]
+    indent: Option<usize>,
+
+    /// Indent with tabs instead of spaces
+    #[arg(long, overrides_with = "spaces")]
+    tabs: bool,
+
+    /// Indent with spaces, even if a .zedfmt asks for tabs
+    #[arg(long, alias = "no-tabs", overrides_with = "tabs")]
+    spaces: bool,
+
+    /// Maximum line length [default: 100]
+    #[arg(long)]
+    max_width: Option<usize>,
+
+    /// Line up the `=` of consecutive single-line assignments
+    #[arg(long, overrides_with = "no_align_assignments")]
+    align_assignments: bool,
+
+    /// Leave assignments unaligned, even if a .zedfmt asks for alignment
+    #[arg(long, overrides_with = "align_assignments")]
+    no_align_assignments: bool,
+
+    /// Sort blocks of @include lines, std includes before local ones
+    #[arg(long, overrides_with = "no_sort_includes")]
+    sort_includes: bool,
+
+    /// Leave includes in their order, even if a .zedfmt asks for sorting
+    #[arg(long, overrides_with = "sort_includes")]
+    no_sort_includes: bool,
+
+    /// Only format lines START through END (1-based, inclusive) of a single file
+    #[arg(long, value_name = "START:END", value_parser = parse_line_range)]
+    range: Option<(usize, usize)>,
+}
+
+fn parse_line_range(range: &str) -> Result<(usize, usize), String> {
+    let (start, end) = range
+        .split_once(':')
+        .ok_or_else(|| "expected START:END".to_string())?;
+    let start: usize = start.trim().parse().map_err(|_| format!("invalid start line '{}'", start))?;
+    let end: usize = end.trim().parse().map_err(|_| format!("invalid end line '{}'", end))?;
 
-    /// Maximum line length
-    #[arg(long, default_value = "100")]
-    max_width: usize,
+    if start == 0 || end < start {
+        return Err("lines are numbered from 1 and START must not be after END".to_string());
+    }
+    Ok((start, end))
 }
 
 fn format_file(path: &Path, config: &formatter::Config, check_only: bool, write: bool) -> Result<bool> {
@@ -40,6 +83,7 @@ fn format_file(path: &Path, config: &formatter::Config, check_only: bool, write:
     if source != formatted {
         if check_only {
             println!("{} {}", "Needs formatting:".red(), path.display());
+            print_diff(path, &source, &formatted);
             return Ok(false);
         }
 
@@ -48,9 +92,8 @@ fn format_file(path: &Path, config: &formatter::Config, check_only: bool, write:
                 .with_context(|| format!("Failed to write to {}", path.display()))?;
             println!("{} {}", "Formatted:".green(), path.display());
         } else {
-            // Print diff
             println!("{} {}", "Would format:".yellow(), path.display());
-            // TODO: Implement diff display
+            print_diff(path, &source, &formatted);
         }
     } else if !check_only {
         println!("{} {}", "Already formatted:".green(), path.display());
@@ -59,22 +102,66 @@ fn format_file(path: &Path, config: &formatter::Config, check_only: bool, write:
     Ok(true)
 }
 
-fn process_paths(paths: &[String], config: &formatter::Config, check: bool, write: bool) -> Result<bool> {
+fn print_diff(path: &Path, source: &str, formatted: &str) {
+    let diff = diff::unified_diff(&path.display().to_string(), source, formatted);
+    if diff.is_empty() {
+        // Only the line endings or the final newline differ
+        println!("  {}", "(line endings only)".dimmed());
+    } else {
+        print!("{}", diff);
+    }
+}
+
+// A setting with a flag to turn it on and one to turn it off, or `None`
+// when neither was given
+fn switch(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+// Settings for one file: the command-line flags, then its nearest
+// `.zedfmt`, then the defaults
+fn config_for(path: &Path, cli: &Cli) -> Result<formatter::Config> {
+    let file = config::FileConfig::discover(path)?;
+    let tabs = switch(cli.tabs, cli.spaces).or(file.tabs).unwrap_or(false);
+    Ok(formatter::Config {
+        indent_style: if tabs {
+            formatter::IndentStyle::Tabs
+        } else {
+            formatter::IndentStyle::Spaces
+        },
+        indent_spaces: cli.indent.or(file.indent_spaces).unwrap_or(4),
+        max_width: cli.max_width.or(file.max_width).unwrap_or(100),
+        line_range: cli.range,
+        align_assignments: switch(cli.align_assignments, cli.no_align_assignments)
+            .or(file.align_assignments)
+            .unwrap_or(false),
+        sort_includes: switch(cli.sort_includes, cli.no_sort_includes)
+            .or(file.sort_includes)
+            .unwrap_or(false),
+    })
+}
+
+fn process_paths(cli: &Cli) -> Result<bool> {
+    let (check, write) = (cli.check, cli.write);
     let mut all_formatted = true;
 
-    for path_str in paths {
+    for path_str in &cli.paths {
         let path = PathBuf::from(path_str);
         if path.is_file() {
-            if !format_file(&path, config, check, write)? {
+            if !format_file(&path, &config_for(&path, cli)?, check, write)? {
                 all_formatted = false;
             }
         } else if path.is_dir() {
             for entry in WalkDir::new(&path)
                 .into_iter()
                 .filter_map(|e| e.ok())
-                .filter(|e| e.path().extension().map_or(false, |ext| ext == "zed"))
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "zed"))
             {
-                if !format_file(entry.path(), config, check, write)? {
+                if !format_file(entry.path(), &config_for(entry.path(), cli)?, check, write)? {
                     all_formatted = false;
                 }
             }
@@ -94,12 +181,11 @@ fn main() -> Result<()> {
         anyhow::bail!("Cannot use both --check and --write");
     }
 
-    let config = formatter::Config {
-        indent_spaces: cli.indent,
-        max_width: cli.max_width,
-    };
+    if cli.range.is_some() && (cli.paths.len() != 1 || !Path::new(&cli.paths[0]).is_file()) {
+        anyhow::bail!("--range can only be used with a single file");
+    }
 
-    let all_formatted = process_paths(&cli.paths, &config, cli.check, cli.write)?;
+    let all_formatted = process_paths(&cli)?;
 
     if cli.check && !all_formatted {
         std::process::exit(1);
@@ -2,8 +2,13 @@ use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use colored::*;
 use flate2::read::GzDecoder;
-use reqwest::blocking::Client;
+use reqwest::blocking::{Client, Response};
+use reqwest::StatusCode;
+use semver::{Version, VersionReq};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
@@ -12,6 +17,8 @@ use tempfile::TempDir;
 use walkdir::WalkDir;
 
 const REGISTRY_URL: &str = "https://zed-pkg.vercel.app/api/packages";
+const REGISTRY_TOKEN_ENV: &str = "ZED_REGISTRY_TOKEN";
+const LOCKFILE: &str = "zed.lock";
 
 #[derive(Parser)]
 #[command(name = "zed-pkg")]
@@ -23,14 +30,22 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Install a package
+    /// Install a package, or every package in zed.lock when none is given
     Install {
         /// Package name
-        package: String,
+        package: Option<String>,
 
-        /// Optional specific version
+        /// Version or range to install, like 1.2.0, ^1.2 or >=1.0, <2.0
         #[arg(short, long)]
         version: Option<String>,
+
+        /// Install from a local package directory instead of the registry
+        #[arg(long, conflicts_with_all = ["package", "version", "file"])]
+        path: Option<PathBuf>,
+
+        /// Install from a local package tarball instead of the registry
+        #[arg(long, conflicts_with_all = ["package", "version"])]
+        file: Option<PathBuf>,
     },
     /// Publish a package to the registry
     Publish {
@@ -42,8 +57,65 @@ enum Commands {
         #[arg(short, long)]
         force: bool,
     },
+    /// Withdraw a published version so new installs skip it
+    Yank {
+        /// Package name
+        package: String,
+
+        /// Version to yank
+        #[arg(long)]
+        version: String,
+
+        /// Restore a previously yanked version
+        #[arg(long)]
+        undo: bool,
+
+        /// Registry API token (default: $ZED_REGISTRY_TOKEN)
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Create a zed.json and starter file for a new package
+    Init {
+        /// Path to the package directory (default: current directory)
+        #[arg(default_value = ".")]
+        path: String,
+
+        /// Package name (default: the directory name)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Initial version
+        #[arg(long, default_value = "0.1.0")]
+        version: String,
+
+        /// Short description of the package
+        #[arg(long)]
+        description: Option<String>,
+
+        /// Package author
+        #[arg(long)]
+        author: Option<String>,
+
+        /// Repository URL
+        #[arg(long)]
+        repository: Option<String>,
+
+        /// Comma-separated keywords
+        #[arg(long, value_delimiter = ',')]
+        keywords: Option<Vec<String>>,
+    },
     /// List installed packages
     List,
+    /// Show installed packages and their dependencies as a tree
+    Tree {
+        /// Only show the tree rooted at this package
+        #[arg(short, long)]
+        package: Option<String>,
+
+        /// Maximum depth of dependencies to show
+        #[arg(long)]
+        depth: Option<usize>,
+    },
     /// Remove a package
     Remove {
         /// Package name
@@ -59,6 +131,178 @@ struct PackageMetadata {
     author: Option<String>,
     repository: Option<String>,
     keywords: Option<Vec<String>>,
+    /// Dependency names mapped to the versions they require
+    #[serde(default)]
+    dependencies: Option<BTreeMap<String, String>>,
+    /// SHA-256 of the published tarball, in hex. `publish` fills this in;
+    /// it isn't written to `zed.json`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+/// The metadata `install` records next to each package in `src/pkg/`.
+#[derive(Debug, Deserialize)]
+struct InstalledPackage {
+    name: String,
+    version: String,
+    #[serde(default)]
+    dependencies: Option<BTreeMap<String, String>>,
+}
+
+/// The exact version and tarball hash of every installed package, written to
+/// `zed.lock` so the same set can be installed again later.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    packages: BTreeMap<String, LockedPackage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LockedPackage {
+    version: String,
+    /// SHA-256 of the package tarball, in hex
+    sha256: String,
+    /// The package's own dependencies, as its metadata requested them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    dependencies: BTreeMap<String, String>,
+}
+
+/// A requested package version: an exact `MAJOR.MINOR.PATCH`, or a range
+/// such as `^1.2`, `~1.2.3` or `>=1.0, <2.0` that's resolved against the
+/// registry's published versions.
+enum VersionSpec {
+    Exact(Version),
+    Range(VersionReq),
+}
+
+impl VersionSpec {
+    fn parse(spec: &str) -> Result<Self> {
+        if let Ok(version) = Version::parse(spec) {
+            return Ok(Self::Exact(version));
+        }
+        VersionReq::parse(spec)
+            .map(Self::Range)
+            .map_err(|e| anyhow::anyhow!(
+                "Invalid version requirement '{}': {} (expected a version like 1.2.0 or a range like ^1.2, ~1.2.3 or >=1.0, <2.0)",
+                spec,
+                e
+            ))
+    }
+
+    fn matches(&self, version: &str) -> bool {
+        let Ok(version) = Version::parse(version) else {
+            return false;
+        };
+        match self {
+            Self::Exact(exact) => *exact == version,
+            Self::Range(range) => range.matches(&version),
+        }
+    }
+}
+
+impl std::fmt::Display for VersionSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exact(version) => write!(f, "v{}", version),
+            Self::Range(range) => write!(f, "{}", range),
+        }
+    }
+}
+
+/// One entry in the registry's list of a package's versions
+#[derive(Debug, Deserialize)]
+struct PublishedVersion {
+    version: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+/// The packages one `install` run needs, worked out before any download
+#[derive(Default)]
+struct Resolution {
+    /// Every package needed, in the order they were found
+    packages: Vec<PackageMetadata>,
+    /// Packages whose dependencies are being resolved, outermost first
+    stack: Vec<String>,
+    /// Versions pinned in `zed.lock`, used instead of the newest match for
+    /// any range they satisfy
+    locked: BTreeMap<String, Version>,
+}
+
+impl Lockfile {
+    /// Reads `zed.lock` from the current directory; a missing lockfile is
+    /// empty.
+    fn read() -> Result<Self> {
+        let path = std::env::current_dir()?.join(LOCKFILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path).context("Failed to read zed.lock")?;
+        serde_json::from_str(&content).context("Invalid zed.lock format")
+    }
+
+    fn write(&self) -> Result<()> {
+        let path = std::env::current_dir()?.join(LOCKFILE);
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .context("Failed to write zed.lock")
+    }
+}
+
+/// Parses a `zed.json` manifest. Strict JSON is tried first; if that fails,
+/// the manifest is parsed as JSON5 so it may contain comments and trailing
+/// commas.
+fn parse_manifest<T: DeserializeOwned>(content: &str) -> Result<T> {
+    match serde_json::from_str(content) {
+        Ok(value) => Ok(value),
+        Err(_) => Ok(json5::from_str(content)?),
+    }
+}
+
+/// Checks the fields the registry relies on, so problems surface before
+/// anything is written or uploaded.
+fn validate_metadata(metadata: &PackageMetadata) -> Result<()> {
+    let name = &metadata.name;
+    if name.is_empty() {
+        anyhow::bail!("Package name is required");
+    }
+    if !name.starts_with(|c: char| c.is_ascii_lowercase())
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+    {
+        anyhow::bail!(
+            "Invalid package name '{}': use lowercase letters, digits, '_' and '-', starting with a letter",
+            name
+        );
+    }
+
+    let version_parts: Vec<&str> = metadata.version.split('.').collect();
+    if version_parts.len() != 3
+        || version_parts
+            .iter()
+            .any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()))
+    {
+        anyhow::bail!(
+            "Invalid version '{}': expected MAJOR.MINOR.PATCH, like 0.1.0",
+            metadata.version
+        );
+    }
+
+    if let Some(repository) = &metadata.repository {
+        if !repository.starts_with("https://") && !repository.starts_with("http://") {
+            anyhow::bail!("Invalid repository '{}': expected an http(s) URL", repository);
+        }
+    }
+
+    for (dependency, version) in metadata.dependencies.iter().flatten() {
+        if dependency == name {
+            anyhow::bail!("Package '{}' can't depend on itself", name);
+        }
+        VersionSpec::parse(version)
+            .with_context(|| format!("Invalid version for dependency '{}'", dependency))?;
+    }
+
+    Ok(())
 }
 
 fn main() -> Result<()> {
@@ -69,71 +313,329 @@ fn main() -> Result<()> {
     fs::create_dir_all(&pkg_dir)?;
 
     match cli.command {
-        Commands::Install { package, version } => install_package(&package, version)?,
+        Commands::Install {
+            path: Some(path), ..
+        } => install_local(LocalSource::Directory(&path))?,
+        Commands::Install {
+            file: Some(file), ..
+        } => install_local(LocalSource::Tarball(&file))?,
+        Commands::Install {
+            package: Some(package),
+            version,
+            ..
+        } => install_package(&package, version)?,
+        Commands::Install { package: None, .. } => install_locked()?,
         Commands::Publish { path, force } => publish_package(&path, force)?,
+        Commands::Yank {
+            package,
+            version,
+            undo,
+            token,
+        } => yank_package(&package, &version, undo, token)?,
+        Commands::Init {
+            path,
+            name,
+            version,
+            description,
+            author,
+            repository,
+            keywords,
+        } => {
+            let name = match name {
+                Some(name) => name,
+                None => package_name_from_path(&path)?,
+            };
+            let metadata = PackageMetadata {
+                name,
+                version,
+                description,
+                author,
+                repository,
+                keywords,
+                dependencies: None,
+                sha256: None,
+            };
+            init_package(&path, &metadata)?
+        }
         Commands::List => list_packages()?,
+        Commands::Tree { package, depth } => print_tree(package.as_deref(), depth)?,
         Commands::Remove { package } => remove_package(&package)?,
     }
 
     Ok(())
 }
 
+/// Installs `package` from the registry along with everything it depends
+/// on, and records each in `zed.lock`. The whole dependency graph is
+/// resolved before anything is downloaded, so a cycle or version conflict
+/// leaves `src/pkg/` untouched.
 fn install_package(package: &str, version: Option<String>) -> Result<()> {
     let client = Client::new();
+    let mut resolution = Resolution::default();
+    resolve(&client, package, version.as_deref(), &mut resolution)?;
+    install_resolved(&client, &resolution, &Lockfile::default())
+}
 
+/// Fetches the metadata of `package` and, recursively, its dependencies
+/// into `resolution`. A package needed twice must be needed at the same
+/// version, and a package can't depend on itself through others.
+fn resolve(client: &Client, package: &str, version: Option<&str>, resolution: &mut Resolution) -> Result<()> {
+    if let Some(start) = resolution.stack.iter().position(|name| name == package) {
+        let cycle: Vec<&str> = resolution.stack[start..]
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(package))
+            .collect();
+        anyhow::bail!("Dependency cycle detected: {}", cycle.join(" -> "));
+    }
+    let mut spec = version.map(VersionSpec::parse).transpose()?;
+    if let (Some(locked), Some(requested)) = (resolution.locked.get(package), &spec) {
+        if requested.matches(&locked.to_string()) {
+            spec = Some(VersionSpec::Exact(locked.clone()));
+        }
+    }
+    if let Some(found) = resolution.packages.iter().find(|found| found.name == package) {
+        match spec {
+            Some(spec) if !spec.matches(&found.version) => anyhow::bail!(
+                "Version conflict: {} requires {} {}, but v{} is already required",
+                resolution.stack.last().map_or("the project", String::as_str),
+                package,
+                spec,
+                found.version
+            ),
+            _ => return Ok(()),
+        }
+    }
+
+    let package_info = fetch_metadata(client, package, spec.as_ref()).with_context(|| {
+        match resolution.stack.last() {
+            Some(parent) => format!("Can't install {}, which {} depends on", package, parent),
+            None => format!("Can't install {}", package),
+        }
+    })?;
+    let dependencies = package_info.dependencies.clone().unwrap_or_default();
+    resolution.packages.push(package_info);
+
+    resolution.stack.push(package.to_string());
+    for (dependency, version) in &dependencies {
+        resolve(client, dependency, Some(version), resolution)?;
+    }
+    resolution.stack.pop();
+    Ok(())
+}
+
+fn fetch_metadata(client: &Client, package: &str, spec: Option<&VersionSpec>) -> Result<PackageMetadata> {
     // Construct URL for package metadata
-    let url = match version {
-        Some(v) => format!("{}/{}/{}", REGISTRY_URL, package, v),
+    let url = match spec {
+        Some(VersionSpec::Exact(version)) => format!("{}/{}/{}", REGISTRY_URL, package, version),
+        Some(VersionSpec::Range(range)) => {
+            let version = pick_version(client, package, range)?;
+            format!("{}/{}/{}", REGISTRY_URL, package, version)
+        }
         None => format!("{}/{}", REGISTRY_URL, package),
     };
 
-    // Fetch package metadata
     let response = client.get(&url)
         .send()
         .context("Failed to fetch package metadata")?;
 
+    if response.status() == StatusCode::NOT_FOUND {
+        anyhow::bail!("Package not found");
+    }
     if !response.status().is_success() {
-        println!("{} Package not found", "✗".red());
-        return Ok(());
+        let status = response.status();
+        anyhow::bail!("Failed to fetch package ({}): {}", status, error_message(response));
     }
 
-    let package_info: PackageMetadata = response.json()?;
+    Ok(response.json()?)
+}
 
-    // Prepare pkg directory
-    let pkg_dir = std::env::current_dir()?.join("src/pkg");
-    let package_file = pkg_dir.join(format!("{}.zed", package_info.name));
+/// Picks the highest published, unyanked version of `package` in `range`.
+fn pick_version(client: &Client, package: &str, range: &VersionReq) -> Result<Version> {
+    let url = format!("{}/{}/versions", REGISTRY_URL, package);
+    let response = client.get(&url)
+        .send()
+        .context("Failed to fetch package versions")?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        anyhow::bail!("Package not found");
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        anyhow::bail!("Failed to fetch package versions ({}): {}", status, error_message(response));
+    }
+
+    let published: Vec<PublishedVersion> = response.json()?;
+    published
+        .iter()
+        .filter(|published| !published.yanked)
+        .filter_map(|published| Version::parse(&published.version).ok())
+        .filter(|version| range.matches(version))
+        .max()
+        .with_context(|| format!("No published version of {} matches {}", package, range))
+}
+
+/// Downloads and installs every resolved package. Packages pinned in
+/// `pinned` at the same version must match its hashes.
+fn install_resolved(client: &Client, resolution: &Resolution, pinned: &Lockfile) -> Result<()> {
+    for package_info in &resolution.packages {
+        let expected = pinned
+            .packages
+            .get(&package_info.name)
+            .filter(|locked| locked.version == package_info.version)
+            .map(|locked| locked.sha256.as_str());
+        download_package(client, package_info, expected)?;
+    }
+    Ok(())
+}
 
+/// Downloads one package, checks its hash against the registry's (and
+/// `expected_sha256`, when restoring `zed.lock`), installs it and records
+/// it in `zed.lock`. A download with nothing to check it against isn't
+/// installed.
+fn download_package(client: &Client, package_info: &PackageMetadata, expected_sha256: Option<&str>) -> Result<()> {
     // Create temporary directory for download
     let temp_dir = TempDir::new()?;
-    let tarball_path = temp_dir.path().join(format!("{}.tar.gz", package));
+    let tarball_path = temp_dir.path().join(format!("{}.tar.gz", package_info.name));
 
-    // Download package tarball
+    // Download package tarball. The body is gzip data, so it's copied to
+    // disk as raw bytes as it arrives rather than decoded as text.
+    let download_url = format!(
+        "{}/{}/{}/download",
+        REGISTRY_URL, package_info.name, package_info.version
+    );
+    let mut download = client.get(&download_url)
+        .send()
+        .context("Failed to download package")?;
+    if !download.status().is_success() {
+        let status = download.status();
+        anyhow::bail!("Failed to download package ({}): {}", status, error_message(download));
+    }
     let mut file = File::create(&tarball_path)?;
-    let download_url = format!("{}/download", url);
-    let content = client.get(&download_url)
-        .send()?
-        .text()?;
-    file.write_all(content.as_bytes())?;
+    download
+        .copy_to(&mut file)
+        .context("Failed to save package download")?;
+
+    let sha256 = file_sha256(&tarball_path)?;
+    if package_info.sha256.is_none() && expected_sha256.is_none() {
+        anyhow::bail!(
+            "The registry lists no SHA-256 for {} v{}, so its download can't be verified; not installing it",
+            package_info.name,
+            package_info.version
+        );
+    }
+    if let Some(published) = &package_info.sha256 {
+        if !sha256.eq_ignore_ascii_case(published) {
+            anyhow::bail!(
+                "Checksum mismatch for {} v{}: the registry lists SHA-256 {} but the download has {}; it may be corrupted or tampered with",
+                package_info.name,
+                package_info.version,
+                published,
+                sha256
+            );
+        }
+    }
+    if let Some(expected) = expected_sha256 {
+        if sha256 != expected {
+            anyhow::bail!(
+                "{} v{} doesn't match zed.lock (expected SHA-256 {}, got {})",
+                package_info.name,
+                package_info.version,
+                expected,
+                sha256
+            );
+        }
+    }
 
-    // Remove existing package file if it exists
-    if package_file.exists() {
-        fs::remove_file(&package_file)?;
+    // Extract to a temporary directory and find the .zed file
+    let extract_dir = temp_dir.path().join("extracted");
+    extract_tarball(&tarball_path, &extract_dir)?;
+    let zed_file = find_zed_file(&extract_dir)?;
+
+    add_to_pkg_dir(package_info, &zed_file, Some(&sha256))?;
+
+    let mut lockfile = Lockfile::read()?;
+    lockfile.packages.insert(
+        package_info.name.clone(),
+        LockedPackage {
+            version: package_info.version.clone(),
+            sha256,
+            dependencies: package_info.dependencies.clone().unwrap_or_default(),
+        },
+    );
+    lockfile.write()
+}
+
+/// Where `install --path` and `install --file` read a package from
+enum LocalSource<'a> {
+    Directory(&'a Path),
+    Tarball(&'a Path),
+}
+
+/// Installs a package from a local directory holding its `zed.json` and
+/// `.zed` file, or from a tarball made by `publish`. Nothing is fetched, and
+/// since the registry can't reproduce the package, it isn't added to
+/// `zed.lock`.
+fn install_local(source: LocalSource) -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (package_dir, tarball) = match source {
+        LocalSource::Directory(path) => (path.to_path_buf(), None),
+        LocalSource::Tarball(tarball) => {
+            let extract_dir = temp_dir.path().join("extracted");
+            extract_tarball(tarball, &extract_dir)
+                .with_context(|| format!("Failed to extract {}", tarball.display()))?;
+            (extract_dir, Some(tarball))
+        }
+    };
+
+    let metadata_path = package_dir.join("zed.json");
+    let metadata_str = fs::read_to_string(&metadata_path)
+        .with_context(|| format!("Failed to read {}", metadata_path.display()))?;
+    let package_info: PackageMetadata = parse_manifest(&metadata_str)
+        .context("Invalid zed.json format")?;
+    validate_metadata(&package_info)?;
+
+    // Prefer the file named after the package, as `init` creates it
+    let named_file = package_dir.join(format!("{}.zed", package_info.name));
+    let zed_file = if named_file.is_file() {
+        named_file
+    } else {
+        find_zed_file(&package_dir)?
+    };
+
+    // Resolve the dependencies first, so a bad graph installs nothing
+    let client = Client::new();
+    let mut resolution = Resolution {
+        stack: vec![package_info.name.clone()],
+        ..Resolution::default()
+    };
+    for (dependency, version) in package_info.dependencies.iter().flatten() {
+        resolve(&client, dependency, Some(version), &mut resolution)?;
     }
 
-    // Extract tarball and find .zed file
-    let tar_gz = File::open(&tarball_path)?;
-    let tar = GzDecoder::new(tar_gz);
-    let mut archive = Archive::new(tar);
+    let sha256 = tarball.map(file_sha256).transpose()?;
+    add_to_pkg_dir(&package_info, &zed_file, sha256.as_deref())?;
+    install_resolved(&client, &resolution, &Lockfile::default())
+}
 
-    // Extract to a temporary directory
-    let extract_dir = temp_dir.path().join("extracted");
-    fs::create_dir_all(&extract_dir)?;
-    archive.unpack(&extract_dir)?;
+fn extract_tarball(tarball: &Path, dest: &Path) -> Result<()> {
+    let tar_gz = File::open(tarball)?;
+    let mut archive = Archive::new(GzDecoder::new(tar_gz));
+    fs::create_dir_all(dest)?;
+    archive.unpack(dest)?;
+    Ok(())
+}
 
-    // Find .zed file in extracted contents
-    let zed_file = find_zed_file(&extract_dir)?;
+/// Copies a package's `.zed` file into `src/pkg/`, replacing any earlier
+/// version, and writes the metadata `list` and `tree` read.
+fn add_to_pkg_dir(package_info: &PackageMetadata, zed_file: &Path, sha256: Option<&str>) -> Result<()> {
+    let pkg_dir = std::env::current_dir()?.join("src/pkg");
+    let package_file = pkg_dir.join(format!("{}.zed", package_info.name));
 
-    // Copy .zed file to src/pkg
+    // Remove existing package file if it exists
+    if package_file.exists() {
+        fs::remove_file(&package_file)?;
+    }
     fs::copy(zed_file, &package_file)?;
 
     println!(
@@ -150,6 +652,8 @@ fn install_package(package: &str, version: Option<String>) -> Result<()> {
         "name": package_info.name,
         "version": package_info.version,
         "description": package_info.description,
+        "dependencies": package_info.dependencies,
+        "sha256": sha256,
         "installed_at": chrono::Utc::now().to_rfc3339()
     });
 
@@ -161,6 +665,41 @@ fn install_package(package: &str, version: Option<String>) -> Result<()> {
     Ok(())
 }
 
+/// Reinstalls exactly the versions pinned in `zed.lock`.
+fn install_locked() -> Result<()> {
+    let lockfile = Lockfile::read()?;
+    if lockfile.packages.is_empty() {
+        println!("No packages in zed.lock; install one with `zed-pkg install <package>`");
+        return Ok(());
+    }
+
+    // Dependencies are resolved with the ranges their packages declare, so
+    // every pinned version has to be known before any of them is walked
+    let client = Client::new();
+    let mut resolution = Resolution::default();
+    for (name, locked) in &lockfile.packages {
+        let version = Version::parse(&locked.version)
+            .with_context(|| format!("Invalid version '{}' for {} in zed.lock", locked.version, name))?;
+        resolution.locked.insert(name.clone(), version);
+    }
+    for (name, locked) in &lockfile.packages {
+        resolve(&client, name, Some(&locked.version), &mut resolution)?;
+    }
+    install_resolved(&client, &resolution, &lockfile)
+}
+
+/// Hex-encoded SHA-256 of a file's contents.
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
 fn publish_package(path: &str, force: bool) -> Result<()> {
     // Read package metadata from zed.json
     let metadata_path = Path::new(path).join("zed.json");
@@ -172,17 +711,12 @@ fn publish_package(path: &str, force: bool) -> Result<()> {
     let metadata_str = fs::read_to_string(&metadata_path)
         .context("Failed to read zed.json")?;
 
-    let metadata: PackageMetadata = serde_json::from_str(&metadata_str)
+    let metadata: PackageMetadata = parse_manifest(&metadata_str)
         .context("Invalid zed.json format")?;
 
     // Validate required fields
-    if metadata.name.is_empty() {
-        println!("{} Package name is required", "Error:".red());
-        return Ok(());
-    }
-
-    if metadata.version.is_empty() {
-        println!("{} Package version is required", "Error:".red());
+    if let Err(e) = validate_metadata(&metadata) {
+        println!("{} {}", "Error:".red(), e);
         return Ok(());
     }
 
@@ -204,8 +738,11 @@ fn publish_package(path: &str, force: bool) -> Result<()> {
         }
     }
 
-    // Create package tarball
+    // Create package tarball, whose checksum is published with the metadata
+    // so installs can verify their download
     let tarball_path = create_package_tarball(path, &metadata)?;
+    let mut metadata = metadata;
+    metadata.sha256 = Some(file_sha256(Path::new(&tarball_path))?);
 
     // Prepare for upload
     let client = Client::new();
@@ -223,8 +760,7 @@ fn publish_package(path: &str, force: bool) -> Result<()> {
     // Enhanced error handling
     if !metadata_response.status().is_success() {
         let status = metadata_response.status();
-        let error_body = metadata_response.text()
-            .unwrap_or_else(|_| "Unable to read error body".to_string());
+        let error_body = error_message(metadata_response);
 
         println!("{} Failed to publish package metadata", "Error:".red());
         println!("Status Code: {}", status);
@@ -253,8 +789,7 @@ fn publish_package(path: &str, force: bool) -> Result<()> {
         println!("{} Package published successfully!", "Success:".green());
     } else {
         let status = upload_response.status();
-        let error_body = upload_response.text()
-            .unwrap_or_else(|_| "Unable to read error body".to_string());
+        let error_body = error_message(upload_response);
 
         println!("{} Failed to upload package file", "Error:".red());
         println!("Status Code: {}", status);
@@ -267,6 +802,130 @@ fn publish_package(path: &str, force: bool) -> Result<()> {
     Ok(())
 }
 
+/// Marks a version as yanked (or un-yanked) in the registry. Yanked versions
+/// stay downloadable when requested explicitly, but aren't picked for new
+/// installs.
+fn yank_package(package: &str, version: &str, undo: bool, token: Option<String>) -> Result<()> {
+    let token = match token.or_else(|| std::env::var(REGISTRY_TOKEN_ENV).ok()) {
+        Some(token) if !token.is_empty() => token,
+        _ => anyhow::bail!(
+            "Yanking requires a registry token; pass --token or set {}",
+            REGISTRY_TOKEN_ENV
+        ),
+    };
+
+    let action = if undo { "unyank" } else { "yank" };
+    let url = format!("{}/{}/{}/{}", REGISTRY_URL, package, version, action);
+
+    let response = Client::new()
+        .post(&url)
+        .bearer_auth(token)
+        .send()
+        .with_context(|| format!("Failed to send {} request", action))?;
+
+    match response.status() {
+        status if status.is_success() => {
+            let verb = if undo { "Unyanked" } else { "Yanked" };
+            println!("{} {} {} v{}", "✓".green(), verb, package.bright_blue(), version);
+            Ok(())
+        }
+        StatusCode::UNAUTHORIZED => {
+            anyhow::bail!("The registry rejected the API token")
+        }
+        StatusCode::FORBIDDEN => {
+            anyhow::bail!("You are not an owner of {}, so you can't {} it", package, action)
+        }
+        StatusCode::NOT_FOUND => {
+            anyhow::bail!("{} v{} was not found in the registry", package, version)
+        }
+        status => anyhow::bail!(
+            "Failed to {} {} v{} ({}): {}",
+            action,
+            package,
+            version,
+            status,
+            error_message(response)
+        ),
+    }
+}
+
+/// Extracts a readable message from a failed registry response. JSON error
+/// bodies are reduced to their `message` or `error` field; anything else is
+/// returned as-is.
+fn error_message(response: Response) -> String {
+    let is_json = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("json"));
+    let body = match response.text() {
+        Ok(body) => body,
+        Err(_) => return "Unable to read error body".to_string(),
+    };
+
+    if is_json {
+        if let Ok(json) = serde_json::from_str::<serde_json::Value>(&body) {
+            if let Some(message) = ["message", "error"]
+                .iter()
+                .find_map(|field| json.get(field).and_then(|value| value.as_str()))
+            {
+                return message.to_string();
+            }
+        }
+    }
+
+    body
+}
+
+/// Uses the package directory's name as the default package name.
+fn package_name_from_path(path: &str) -> Result<String> {
+    let dir = if Path::new(path).exists() {
+        fs::canonicalize(path).with_context(|| format!("Failed to resolve {}", path))?
+    } else {
+        PathBuf::from(path)
+    };
+    let name = dir
+        .file_name()
+        .context("Can't infer a package name from this directory; pass --name")?;
+    Ok(name.to_string_lossy().to_lowercase().replace(' ', "-"))
+}
+
+fn init_package(path: &str, metadata: &PackageMetadata) -> Result<()> {
+    validate_metadata(metadata)?;
+
+    let dir = Path::new(path);
+    let metadata_path = dir.join("zed.json");
+    if metadata_path.exists() {
+        anyhow::bail!("{} already exists", metadata_path.display());
+    }
+
+    fs::create_dir_all(dir)?;
+    fs::write(&metadata_path, serde_json::to_string_pretty(metadata)? + "\n")
+        .context("Failed to write zed.json")?;
+
+    // Starter source file, unless the package already has one
+    let source_path = dir.join(format!("{}.zed", metadata.name));
+    if !source_path.exists() {
+        let source = format!(
+            "/** {} */\n\n/** Returns the answer. */\nfn {}_answer() {{\n    return 42;\n}}\n",
+            metadata.description.as_deref().unwrap_or(&metadata.name),
+            metadata.name.replace('-', "_")
+        );
+        fs::write(&source_path, source)
+            .with_context(|| format!("Failed to write {}", source_path.display()))?;
+    }
+
+    println!(
+        "{} Created package {} v{} in {}",
+        "✓".green(),
+        metadata.name.bright_blue(),
+        metadata.version.bright_blue(),
+        dir.display()
+    );
+
+    Ok(())
+}
+
 fn create_package_tarball(path: &str, metadata: &PackageMetadata) -> Result<String> {
     let tarball_filename = format!("{}-{}.tar.gz", metadata.name, metadata.version);
     let tarball_path = std::env::temp_dir().join(tarball_filename);
@@ -288,7 +947,7 @@ fn find_zed_file(dir: &Path) -> Result<PathBuf> {
         let entry = entry?;
         if entry.file_type().is_file() {
             let path = entry.path();
-            if path.extension().map_or(false, |ext| ext == "zed") {
+            if path.extension().is_some_and(|ext| ext == "zed") {
                 return Ok(path.to_path_buf());
             }
         }
@@ -311,7 +970,7 @@ fn list_packages() -> Result<()> {
         let path = entry.path();
 
         // Look for .json metadata files
-        if path.extension().map_or(false, |ext| ext == "json") {
+        if path.extension().is_some_and(|ext| ext == "json") {
             package_found = true;
 
             // Read metadata
@@ -337,6 +996,134 @@ fn list_packages() -> Result<()> {
     Ok(())
 }
 
+/// Reads the metadata of every package installed in `src/pkg/`, keyed by name.
+fn installed_packages() -> Result<BTreeMap<String, InstalledPackage>> {
+    let pkg_dir = std::env::current_dir()?.join("src/pkg");
+    let mut packages = BTreeMap::new();
+
+    for entry in fs::read_dir(&pkg_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let content = fs::read_to_string(&path)?;
+            let package: InstalledPackage = serde_json::from_str(&content)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            packages.insert(package.name.clone(), package);
+        }
+    }
+
+    Ok(packages)
+}
+
+fn print_tree(root: Option<&str>, depth: Option<usize>) -> Result<()> {
+    let packages = installed_packages()?;
+    if packages.is_empty() {
+        println!("No packages found in src/pkg/ directory.");
+        return Ok(());
+    }
+
+    // Without a root, start from the packages nothing else depends on
+    let roots: Vec<&str> = match root {
+        Some(name) => {
+            if !packages.contains_key(name) {
+                println!("{} Package {} not found in src/pkg/", "✗".red(), name);
+                return Ok(());
+            }
+            vec![name]
+        }
+        None => {
+            let dependents: HashSet<&str> = packages
+                .values()
+                .flat_map(|package| package.dependencies.iter().flat_map(|deps| deps.keys()))
+                .map(String::as_str)
+                .collect();
+            let roots: Vec<&str> = packages
+                .keys()
+                .map(String::as_str)
+                .filter(|name| !dependents.contains(name))
+                .collect();
+            // Every package is part of a cycle; show them all
+            if roots.is_empty() {
+                packages.keys().map(String::as_str).collect()
+            } else {
+                roots
+            }
+        }
+    };
+
+    let mut lines = Vec::new();
+    let mut shown = HashSet::new();
+    for name in roots {
+        render_tree(&packages, name, "", None, depth, &mut shown, &mut lines);
+    }
+    for line in lines {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+/// Renders `name` and its dependencies in the style of `cargo tree`. A
+/// package that already appeared is marked `(*)` instead of being expanded
+/// again, which also stops cycles.
+fn render_tree<'a>(
+    packages: &'a BTreeMap<String, InstalledPackage>,
+    name: &'a str,
+    prefix: &str,
+    branch: Option<bool>,
+    depth: Option<usize>,
+    shown: &mut HashSet<&'a str>,
+    lines: &mut Vec<String>,
+) {
+    let connector = match branch {
+        None => "",
+        Some(true) => "└── ",
+        Some(false) => "├── ",
+    };
+
+    let Some(package) = packages.get(name) else {
+        lines.push(format!("{}{}{} {}", prefix, connector, name, "(not installed)".red()));
+        return;
+    };
+
+    let dependencies: Vec<&'a str> = package
+        .dependencies
+        .iter()
+        .flat_map(|deps| deps.keys())
+        .map(String::as_str)
+        .collect();
+    let repeated = !shown.insert(name);
+    lines.push(format!(
+        "{}{}{} v{}{}",
+        prefix,
+        connector,
+        name.bright_green(),
+        package.version.bright_blue(),
+        if repeated { " (*)" } else { "" }
+    ));
+
+    if repeated || depth == Some(0) {
+        return;
+    }
+
+    let child_prefix = match branch {
+        None => prefix.to_string(),
+        Some(true) => format!("{}    ", prefix),
+        Some(false) => format!("{}│   ", prefix),
+    };
+    for (index, dependency) in dependencies.iter().enumerate() {
+        let last = index + 1 == dependencies.len();
+        render_tree(
+            packages,
+            dependency,
+            &child_prefix,
+            Some(last),
+            depth.map(|d| d - 1),
+            shown,
+            lines,
+        );
+    }
+}
+
 fn remove_package(package: &str) -> Result<()> {
     let pkg_dir = std::env::current_dir()?.join("src/pkg");
     let zed_file = pkg_dir.join(format!("{}.zed", package));
@@ -353,6 +1140,11 @@ fn remove_package(package: &str) -> Result<()> {
         fs::remove_file(&json_file)?;
     }
 
+    let mut lockfile = Lockfile::read()?;
+    if lockfile.packages.remove(package).is_some() {
+        lockfile.write()?;
+    }
+
     println!("{} Removed package {} from src/pkg/", "✓".green(), package.bright_blue());
 
     Ok(())